@@ -2,7 +2,10 @@
 
 package main
 
-import "syscall"
+import (
+	"errors"
+	"syscall"
+)
 
 func getUnixFreeSpace(path string, reserve int64) int64 {
 	// For Unix systems, use the statvfs system call
@@ -21,6 +24,11 @@ func getUnixFreeSpace(path string, reserve int64) int64 {
 	return free
 }
 
+// isDiskFull reports whether err means the destination ran out of space (or quota).
+func isDiskFull(err error) bool {
+	return errors.Is(err, syscall.ENOSPC) || errors.Is(err, syscall.EDQUOT)
+}
+
 // getWindowsFreeSpace is a stub on non-Windows platforms to satisfy references.
 func getWindowsFreeSpace(path string, reserve int64) int64 {
 	return 0
@@ -4,6 +4,7 @@
 package main
 
 import (
+	"errors"
 	"fmt"
 	"os"
 	"os/exec"
@@ -19,6 +20,17 @@ func getUnixFreeSpace(path string, reserve int64) int64 {
 	return 0
 }
 
+// Win32 error codes for a full volume (winerror.h)
+const (
+	errorHandleDiskFull syscall.Errno = 39
+	errorDiskFull       syscall.Errno = 112
+)
+
+// isDiskFull reports whether err means the destination ran out of space.
+func isDiskFull(err error) bool {
+	return errors.Is(err, errorDiskFull) || errors.Is(err, errorHandleDiskFull)
+}
+
 func getWindowsFreeSpace(path string, reserve int64) int64 {
 	// Get the root path of the drive
 	absPath, err := filepath.Abs(path)
@@ -0,0 +1,118 @@
+package main
+
+import (
+	"bufio"
+	"fmt"
+	"os"
+	"path/filepath"
+	"strings"
+)
+
+// excludeRule is one line of an --exclude-from file.
+type excludeRule struct {
+	pattern string   // lower-cased glob
+	segs    []string // pattern split at separators, for rules that are not base
+	negate  bool     // "!pattern": re-include what earlier lines excluded
+	dirOnly bool     // "pattern/": only matches folders
+	base    bool     // no separator: matched against the entry name, not the path below the source
+}
+
+// loadExcludeFile parses a gitignore-like exclude file: one glob per line, blank
+// lines and "#" comments ignored, "!" negates, a trailing "/" limits a line to
+// folders. Globs without a separator match names anywhere in the tree, the
+// others are anchored to the source folder ("docs/*.tmp", "/build"), where a
+// "**" segment stands for any number of folders ("**/build", "docs/**/*.tmp").
+func loadExcludeFile(path string) ([]excludeRule, error) {
+	f, err := os.Open(path)
+	if err != nil {
+		return nil, err
+	}
+	defer f.Close()
+	var rules []excludeRule
+	sc := bufio.NewScanner(f)
+	for n := 1; sc.Scan(); n++ {
+		line := strings.TrimSpace(sc.Text())
+		if line == "" || strings.HasPrefix(line, "#") {
+			continue
+		}
+		var r excludeRule
+		if rest, ok := strings.CutPrefix(line, "!"); ok {
+			r.negate, line = true, rest
+		}
+		if rest, ok := strings.CutSuffix(line, "/"); ok {
+			r.dirOnly, line = true, rest
+		}
+		line = filepath.FromSlash(line)
+		if line == "" {
+			continue
+		}
+		if _, err := filepath.Match(line, ""); err != nil {
+			return nil, fmt.Errorf("%s:%d: bad pattern %q: %w", path, n, line, err)
+		}
+		r.base = !strings.ContainsRune(line, filepath.Separator)
+		// "/build" anchors like "build/x" does; paths below the source have no leading separator
+		line = strings.TrimPrefix(line, string(filepath.Separator))
+		r.pattern = strings.ToLower(line)
+		r.segs = strings.Split(r.pattern, string(filepath.Separator))
+		rules = append(rules, r)
+	}
+	return rules, sc.Err()
+}
+
+// excludedBy applies rules to full, an entry below the source folder root; as
+// in .gitignore the last matching line wins.
+func excludedBy(rules []excludeRule, root, full string, isDir bool) bool {
+	if len(rules) == 0 {
+		return false
+	}
+	rel, err := filepath.Rel(root, full)
+	if err != nil {
+		rel = full
+	}
+	lower := strings.ToLower(rel)
+	name := filepath.Base(lower)
+	segs := strings.Split(lower, string(filepath.Separator))
+	excluded := false
+	for _, r := range rules {
+		if r.dirOnly && !isDir {
+			continue
+		}
+		var ok bool
+		if r.base {
+			ok, _ = filepath.Match(r.pattern, name)
+		} else {
+			ok = matchSegments(r.segs, segs)
+		}
+		if ok {
+			excluded = !r.negate
+		}
+	}
+	return excluded
+}
+
+// matchSegments matches path segments against glob segments, where a "**"
+// segment matches any number of path segments (at least one at the end, so
+// "docs/**" matches what is inside docs but not docs itself).
+func matchSegments(pat, path []string) bool {
+	for len(pat) > 0 {
+		if pat[0] == "**" {
+			if len(pat) == 1 {
+				return len(path) > 0
+			}
+			for i := 0; i <= len(path); i++ {
+				if matchSegments(pat[1:], path[i:]) {
+					return true
+				}
+			}
+			return false
+		}
+		if len(path) == 0 {
+			return false
+		}
+		if ok, _ := filepath.Match(pat[0], path[0]); !ok {
+			return false
+		}
+		pat, path = pat[1:], path[1:]
+	}
+	return len(path) == 0
+}
@@ -0,0 +1,62 @@
+package main
+
+import (
+	"os"
+	"path/filepath"
+	"strings"
+	"testing"
+)
+
+func TestExcludedBy(t *testing.T) {
+	path := filepath.Join(t.TempDir(), "excludes.txt")
+	rules := strings.Join([]string{
+		"# comment",
+		"*.iso",
+		"!keep.iso",
+		"docs/*.tmp",
+		"/build",
+		"cache/",
+		"**/node_modules",
+		"logs/**/*.log",
+		"tmp/**",
+	}, "\n")
+	if err := os.WriteFile(path, []byte(rules), 0o644); err != nil {
+		t.Fatal(err)
+	}
+	parsed, err := loadExcludeFile(path)
+	if err != nil {
+		t.Fatal(err)
+	}
+
+	root := filepath.FromSlash("/home/me/src")
+	for _, tc := range []struct {
+		rel   string
+		isDir bool
+		want  bool
+	}{
+		{"disk.iso", false, true},      // base name, top level
+		{"a/b/Disk.ISO", false, true},  // base name at any depth, any case
+		{"a/keep.iso", false, false},   // re-included by the later "!" line
+		{"docs/x.tmp", false, true},    // anchored to the source folder
+		{"a/docs/x.tmp", false, false}, // anchored, so not matched deeper down
+		{"build", true, true},          // leading "/" anchors too
+		{"a/build", true, false},
+		{"cache", true, true},   // "dir/" matches folders...
+		{"cache", false, false}, // ...but not files
+		{"a/cache", true, true}, // at any depth
+		{"notes.txt", false, false},
+		{"src/docs/x.tmp", false, false}, // the root's own name is not part of the match
+		{"node_modules", true, true},     // "**/" matches at the top level...
+		{"a/b/node_modules", true, true}, // ...and at any depth
+		{"logs/x.log", false, true},      // "/**/" matches zero folders...
+		{"logs/2024/01/x.log", false, true},
+		{"logs/x.txt", false, false},
+		{"tmp", true, false}, // "/**" matches what is inside, not the folder itself
+		{"tmp/a/b.txt", false, true},
+	} {
+		full := filepath.Join(root, filepath.FromSlash(tc.rel))
+		if got := excludedBy(parsed, root, full, tc.isDir); got != tc.want {
+			t.Errorf("excludedBy(%q, dir=%v) = %v, want %v", tc.rel, tc.isDir, got, tc.want)
+		}
+	}
+}
@@ -0,0 +1,29 @@
+//go:build windows
+
+package main
+
+import (
+	"os"
+
+	"golang.org/x/sys/windows"
+)
+
+// fileID returns the volume serial number and file index of path and reports
+// whether the file has more than one hard link (only those are worth tracking).
+func fileID(path string, fi os.FileInfo) (fileKey, bool) {
+	p, err := windows.UTF16PtrFromString(longPath(path))
+	if err != nil {
+		return fileKey{}, false
+	}
+	// Zero access is enough to query file information (same as os.SameFile)
+	h, err := windows.CreateFile(p, 0, 0, nil, windows.OPEN_EXISTING, windows.FILE_FLAG_BACKUP_SEMANTICS, 0)
+	if err != nil {
+		return fileKey{}, false
+	}
+	defer windows.CloseHandle(h)
+	var d windows.ByHandleFileInformation
+	if err := windows.GetFileInformationByHandle(h, &d); err != nil || d.NumberOfLinks < 2 {
+		return fileKey{}, false
+	}
+	return fileKey{dev: uint64(d.VolumeSerialNumber), ino: uint64(d.FileIndexHigh)<<32 | uint64(d.FileIndexLow)}, true
+}
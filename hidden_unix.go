@@ -0,0 +1,10 @@
+//go:build !windows
+
+package main
+
+import "io/fs"
+
+// isHidden follows the Unix convention that dot-files are hidden.
+func isHidden(name string, e fs.DirEntry) bool {
+	return len(name) > 1 && name[0] == '.'
+}
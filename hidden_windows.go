@@ -0,0 +1,21 @@
+//go:build windows
+
+package main
+
+import (
+	"io/fs"
+	"syscall"
+)
+
+// isHidden reports whether the entry carries the Hidden or System attribute
+// (desktop.ini, Thumbs.db, $RECYCLE.BIN, ...).
+func isHidden(name string, e fs.DirEntry) bool {
+	info, err := e.Info()
+	if err != nil {
+		return false
+	}
+	if a, ok := info.Sys().(*syscall.Win32FileAttributeData); ok {
+		return a.FileAttributes&(syscall.FILE_ATTRIBUTE_HIDDEN|syscall.FILE_ATTRIBUTE_SYSTEM) != 0
+	}
+	return false
+}
@@ -2,12 +2,17 @@ package main
 
 import (
 	"bufio"
+	"bytes"
 	"context"
+	"crypto/sha256"
+	"encoding/csv"
 	"encoding/json"
+	"errors"
 	"flag"
 	"fmt"
 	"io"
 	"io/fs"
+	"math"
 	"os"
 	"os/exec"
 	"os/signal"
@@ -46,6 +51,7 @@ type ManifestRec struct {
 	Priority int     `json:"priority"`
 	Status   string  `json:"status"`
 	Message  string  `json:"message"`
+	Code     int     `json:"code,omitempty"` // OS error number of a failure (errno / Windows error code)
 	Ts       float64 `json:"ts"`
 }
 
@@ -62,19 +68,50 @@ var (
 	}
 )
 
+// Exit codes besides 0 (success) and 1 (fatal error, see fail).
+const (
+	exitCopyErrors     = 2 // some files failed to copy or were not attempted
+	exitVerifyMismatch = 3 // --verify-only found differences or files missing from the backup
+)
+
 // fastSSDMode toggles runtime heuristics for very fast SSD/NVMe devices.
 var fastSSDMode bool
 var noProgress bool
 var boostMode bool
 
+// skipLocked reports files held open exclusively by another process as skipped rather than failed.
+var skipLocked bool
+
+// fileTimeout abandons a single file copy that takes longer than this (0 = no limit).
+var fileTimeout time.Duration
+
+// compareContent makes same-size destination files count as up to date only if their bytes match.
+var compareContent bool
+
+// durable flushes every copied file, and its folder, to the device before moving on.
+var durable bool
+
+// progressInterval is how often the total and per-file progress lines are logged.
+var progressInterval = time.Second
+
+// stdout receives all human-readable output; --json points it at stderr so that
+// os.Stdout carries only the summary.
+var stdout = os.Stdout
+
 func main() {
 	// Flags
 	sourcesFlag := flag.String("sources", defaultHome(), "Comma-separated source directories to scan")
 	objective := flag.String("objective", "count", "Selection objective: count|space")
 	excludeFlag := flag.String("exclude", "", "Comma-separated extra exclude glob patterns (full path)")
+	excludeFrom := flag.String("exclude-from", "", "File with exclude patterns, one per line (# comments, !pattern re-includes, pattern/ for folders only, ** for any number of folders)")
 	profile := flag.String("profile", "importance_profile.json", "Importance profile JSON path (on USB or absolute)")
 	destSubdir := flag.String("dest-subdir", "", "Destination subfolder on USB; if empty, auto-named unless --resume")
 	dryRun := flag.Bool("dry-run", false, "Plan only, do not copy")
+	verifyOnly := flag.Bool("verify-only", false, "Compare an existing backup (--dest-subdir) with the sources byte by byte, copy nothing")
+	verifyMin := flag.String("verify-min-size", "", "With --verify-only, compare content only of files at least this big (smaller ones by size)")
+	verifyMax := flag.String("verify-max-size", "", "With --verify-only, compare content only of files up to this size (e.g. 1GB; bigger ones by size)")
+	maxFiles := flag.Int("max-files", 0, "Copy at most this many files in this run; the rest is left for --resume (0 = no limit)")
+	maxBytesFlag := flag.String("max-bytes", "", "Copy at most this much data in this run, e.g. 20GB; the rest is left for --resume")
 	resume := flag.Bool("resume", false, "Resume into existing dest-subdir (no new dir)")
 	workers := flag.Int("workers", 0, "Concurrent copy workers (0=auto: all CPU cores)")
 	reserve := flag.Int64("reserve", 0, "Reserve bytes to leave free on USB (default 0 for maximum space)")
@@ -82,8 +119,41 @@ func main() {
 	fastSSD := flag.Bool("fast-ssd", false, "Optimize copy heuristics for very fast SSD/NVMe (fewer syscalls on large files)")
 	boost := flag.Bool("boost", false, "High-performance mode: raise process priority, enable fast-ssd heuristics, keep GUI")
 	noOneDrive := flag.Bool("no-onedrive", false, "Exclude OneDrive folders and variations from scan")
+	noHidden := flag.Bool("no-hidden", false, "Exclude hidden files and folders (dot-files; Hidden/System attribute on Windows)")
+	failFast := flag.Bool("fail-fast", false, "Stop the copy at the first file error instead of continuing with the rest")
+	minSize := flag.String("min-size", "", "Skip files smaller than this size (e.g. 10KB, 5MB)")
+	maxSize := flag.String("max-size", "", "Skip files larger than this size (e.g. 500MB, 2GB)")
+	newerThan := flag.String("newer-than", "", "Only files modified after this date (YYYY-MM-DD) or within this age (e.g. 36h, 30d)")
+	olderThan := flag.String("older-than", "", "Only files modified before this date (YYYY-MM-DD) or at least this old (e.g. 36h, 30d)")
+	marker := flag.String("exclude-marker", "", "Skip any folder (and everything below it) that contains a file with this name, e.g. .nobackup")
+	maxDepth := flag.Int("max-depth", -1, "Descend at most this many folder levels below each source (0 = only files directly in it; -1 = no limit)")
+	sourceFolders := flag.Bool("source-folders", false, "Place each source under a subfolder named after it instead of merging all sources into one tree")
+	dedupe := flag.Bool("dedupe", false, "Store files with identical content once and hard-link the duplicates (hashes same-size files before copying)")
+	flatten := flag.Bool("flatten", false, "Copy every file directly into the destination folder, dropping the source directory structure")
+	preserveHardlinks := flag.Bool("preserve-hardlinks", false, "Recreate hard links between source files at the destination instead of copying the data again")
+	keepEmptyDirs := flag.Bool("keep-empty-dirs", false, "Recreate empty source directories at the destination")
+	noDirTimes := flag.Bool("no-dir-times", false, "Do not copy directory modification times to the destination")
+	bufSize := flag.String("buffer-size", "", "Copy buffer size for large files, e.g. 1MB or 16MB (default 8MB, clamped to 64KB-64MB)")
+	progressEvery := flag.Duration("progress-interval", time.Second, "How often total and per-file progress lines are logged, e.g. 5s or 1m (the TUI bar itself redraws continuously)")
+	fileTimeoutFlag := flag.Duration("file-timeout", 0, "Give up on a single file after this long, e.g. 10m (for flaky network shares; 0 = no limit)")
+	skipLock := flag.Bool("skip-locked", false, "Count files locked by another process (Windows sharing violation) as skipped instead of errors")
+	compare := flag.Bool("compare-content", false, "Compare bytes of same-size destination files and recopy them if they differ (reads both files)")
+	syncTimes := flag.Bool("sync-times", false, "For files already on the destination, copy the source modification time if it differs instead of leaving it")
+	fsync := flag.Bool("fsync", false, "Flush each copied file and its folder to the USB before counting it done (survives power loss; slower)")
+	renameCaseClashes := flag.Bool("rename-case-clashes", false, "Copy files whose name differs only by letter case from another's as \"name (n).ext\" instead of reporting them as errors")
+	keepBoth := flag.Bool("keep-both", false, "Keep differing destination files; write the new copy as \"name (n).ext\" instead of replacing")
+	reportCSV := flag.String("report-csv", "", "Also write this run's per-file results (path, size, status, message, error code) to a CSV file")
+	jsonSummary := flag.Bool("json", false, "Print a JSON summary on stdout when done; all other output goes to stderr (implies --no-progress)")
 	flag.Parse()
 
+	// --json keeps os.Stdout for the summary alone
+	var jsonOut *os.File
+	if *jsonSummary {
+		*noProg = true
+		jsonOut = os.Stdout
+		stdout = os.Stderr
+	}
+
 	if *noProg {
 		noProgress = true
 	}
@@ -92,6 +162,59 @@ func main() {
 		boostMode = true
 	}
 
+	if *compare {
+		compareContent = true
+	}
+
+	if *skipLock {
+		skipLocked = true
+	}
+
+	if *fsync {
+		durable = true
+	}
+
+	fileTimeout = *fileTimeoutFlag
+
+	if *progressEvery <= 0 {
+		fail(fmt.Errorf("--progress-interval must be positive, got %s", *progressEvery))
+	}
+	progressInterval = *progressEvery
+
+	if *verifyOnly && *destSubdir == "" {
+		// without it the whole USB root would be compared with the sources
+		fail(fmt.Errorf("--verify-only requires --dest-subdir"))
+	}
+	if (*verifyMin != "" || *verifyMax != "") && !*verifyOnly {
+		fail(fmt.Errorf("--verify-min-size and --verify-max-size only apply to --verify-only"))
+	}
+
+	// Open the report up front so a bad path fails before the scan, not after it
+	var report *csv.Writer
+	if *reportCSV != "" {
+		if *dryRun || *verifyOnly {
+			fail(fmt.Errorf("--report-csv records copy results and cannot be used with --dry-run or --verify-only"))
+		}
+		rf, err := os.Create(*reportCSV)
+		mustNoErr(err)
+		defer rf.Close()
+		report = csv.NewWriter(rf)
+		mustNoErr(report.Write(reportHeader))
+	}
+
+	if *bufSize != "" {
+		n, err := parseSize(*bufSize)
+		mustNoErr(err)
+		// Tiny buffers multiply syscalls, huge ones multiply memory per worker
+		if n < minCopyBufSize {
+			n = minCopyBufSize
+		}
+		if n > maxCopyBufSize {
+			n = maxCopyBufSize
+		}
+		copyBufSize = int(n)
+	}
+
 	if *fastSSD || boostMode {
 		fastSSDMode = true
 		// Adjust thresholds for high-throughput media: treat more files as "small" to collapse loop overhead
@@ -122,6 +245,7 @@ func main() {
 		if strings.Contains(destDir, "..") || strings.HasPrefix(destDir, string(os.PathSeparator)) || strings.HasPrefix(destDir, "/") {
 			fail(fmt.Errorf("invalid destination subdirectory: path traversal detected"))
 		}
+		mustNoErr(validateSubdir(destDir))
 		destDir = filepath.Join(usbRoot, destDir)
 		// Verify the result is still under usbRoot after joining
 		realDestDir, err := filepath.Abs(destDir)
@@ -132,7 +256,12 @@ func main() {
 	} else {
 		destDir = usbRoot
 	}
-	mustNoErr(os.MkdirAll(destDir, 0o755))
+	if *verifyOnly {
+		// a verify run never writes, so a mistyped subdir must not become an empty folder
+		mustNoErr(checkVerifyDest(destDir))
+	} else {
+		mustNoErr(os.MkdirAll(destDir, 0o755))
+	}
 
 	// Load importance tiers
 	profilePath := *profile
@@ -151,9 +280,9 @@ func main() {
 	}
 	tiers, _ := loadImportanceProfile(profilePath)
 
-	fmt.Printf("USB root: %s\n", usbRoot)
-	fmt.Printf("Destination: %s\n", destDir)
-	fmt.Printf("Free space (usable): %s\n", humanSize(free))
+	fmt.Fprintf(stdout, "USB root: %s\n", usbRoot)
+	fmt.Fprintf(stdout, "Destination: %s\n", destDir)
+	fmt.Fprintf(stdout, "Free space (usable): %s\n", humanSize(free))
 
 	// Parse sources and excludes
 	sources := splitNonEmpty(*sourcesFlag)
@@ -166,6 +295,43 @@ func main() {
 	}
 	excludes = append(excludes, splitNonEmpty(*excludeFlag)...)
 
+	// Exclude file, size, age and hidden-file filters
+	opts := scanOptions{skipHidden: *noHidden, maxDepth: *maxDepth, marker: *marker}
+	if *excludeFrom != "" {
+		opts.rules, err = loadExcludeFile(expandPath(*excludeFrom))
+		mustNoErr(err)
+	}
+	if *minSize != "" {
+		opts.minSize, err = parseSize(*minSize)
+		mustNoErr(err)
+	}
+	if *maxSize != "" {
+		opts.maxSize, err = parseSize(*maxSize)
+		mustNoErr(err)
+	}
+	var maxBytes int64
+	if *maxBytesFlag != "" {
+		maxBytes, err = parseSize(*maxBytesFlag)
+		mustNoErr(err)
+	}
+	var limits verifyRange
+	if *verifyMin != "" {
+		limits.min, err = parseSize(*verifyMin)
+		mustNoErr(err)
+	}
+	if *verifyMax != "" {
+		limits.max, err = parseSize(*verifyMax)
+		mustNoErr(err)
+	}
+	if *newerThan != "" {
+		opts.newerThan, err = parseCutoff(*newerThan, time.Now())
+		mustNoErr(err)
+	}
+	if *olderThan != "" {
+		opts.olderThan, err = parseCutoff(*olderThan, time.Now())
+		mustNoErr(err)
+	}
+
 	// Create cancellable context and handle Ctrl+C
 	ctx, cancel := context.WithCancel(context.Background())
 	defer cancel()
@@ -202,40 +368,128 @@ func main() {
 	if tui != nil {
 		tui.AppendLog("Starting scan...")
 	}
-	files := scanSources(ctx, sources, tiers, excludes, usbRoot, tui)
+	scan := scanSources(ctx, sources, tiers, excludes, opts, usbRoot, tui)
+	files := scan.files
 	t1 := time.Since(t0)
 	var totalBytes int64
 	for _, f := range files {
 		totalBytes += f.Size
 	}
-	fmt.Printf("Scanned %d files in %.2fs (%s total)\n", len(files), t1.Seconds(), humanSize(totalBytes))
+	fmt.Fprintf(stdout, "Scanned %d files in %.2fs (%s total)\n", len(files), t1.Seconds(), humanSize(totalBytes))
+	if len(scan.unreadable) > 0 {
+		fmt.Fprintf(stdout, "Could not read %d folder(s); their contents are not backed up:\n", len(scan.unreadable))
+		for i, f := range scan.unreadable {
+			if i == maxListedFailures {
+				fmt.Fprintf(stdout, "  ... and %d more\n", len(scan.unreadable)-i)
+				break
+			}
+			fmt.Fprintf(stdout, "  %s: %s\n", f.path, f.message)
+		}
+	}
 
 	// Select
-	selected, used := selectFiles(files, free, *objective)
-	fmt.Printf("Selected %d files totalling %s (objective: %s)\n", len(selected), humanSize(used), *objective)
+	var selected []FileInfoRec
+	if *verifyOnly {
+		// Check whatever part of the scan is on the destination, not what would fit now
+		selected = files
+	} else {
+		var used int64
+		selected, used = selectFiles(files, free, *objective)
+		fmt.Fprintf(stdout, "Selected %d files totalling %s (objective: %s)\n", len(selected), humanSize(used), *objective)
+	}
 
 	// Plans
+	var rootNames map[string]string
+	if *sourceFolders {
+		rootNames = sourceFolderNames(sources)
+	}
 	plans := make([][2]string, 0, len(selected)) // [src, dst]
 	for _, fi := range selected {
-		rel := relativeDestPath(fi.Path, sources)
+		rel := relativeDestPath(fi.Path, sources, rootNames)
 		dst := filepath.Join(destDir, rel)
 		plans = append(plans, [2]string{fi.Path, dst})
 	}
+	plans, clashes := dropDuplicateDestinations(plans)
+	if *flatten {
+		// clashes are numbered apart like any other equal names
+		flattenPlans(plans, destDir)
+	} else if len(clashes) > 0 {
+		// Two different files would end up at one path and the later copy would
+		// replace the earlier; nothing has been written yet, so stop here. Leave
+		// the alt screen first so the list stays visible
+		tui.Close()
+		fmt.Fprintf(os.Stderr, "%d destination path(s) are planned for more than one source file:\n", len(clashes))
+		for i, c := range clashes {
+			if i == maxListedFailures {
+				fmt.Fprintf(os.Stderr, "  ... and %d more\n", len(clashes)-i)
+				break
+			}
+			fmt.Fprintf(os.Stderr, "  %s\n", c)
+		}
+		if *sourceFolders {
+			fail(fmt.Errorf("different source files map to the same destination path; rename or drop one of the sources"))
+		}
+		fail(fmt.Errorf("different source files map to the same destination path; use --source-folders to keep each source in its own folder"))
+	}
+	// Only a destination that folds case (FAT/exFAT, NTFS) merges File.txt and
+	// file.txt; verify writes nothing, so it does not probe
+	var renamed []string
+	var caseClashes []copyFailure
+	if !*verifyOnly && destFoldsCase(destDir) {
+		plans, renamed, caseClashes = resolveCaseCollisions(plans, *renameCaseClashes)
+	}
+	if len(renamed) > 0 {
+		fmt.Fprintf(stdout, "Renamed %d file(s) whose names differ only by case (the USB filesystem would merge them):\n", len(renamed))
+		for i, r := range renamed {
+			if i == maxListedFailures {
+				fmt.Fprintf(stdout, "  ... and %d more\n", len(renamed)-i)
+				break
+			}
+			fmt.Fprintf(stdout, "  %s\n", r)
+		}
+	}
+	if len(caseClashes) > 0 {
+		fmt.Fprintf(os.Stderr, "Leaving out %d file(s) whose names differ only by case from another (the USB filesystem would merge them); --rename-case-clashes copies them as \"name (n).ext\":\n", len(caseClashes))
+		for i, c := range caseClashes {
+			if i == maxListedFailures {
+				fmt.Fprintf(os.Stderr, "  ... and %d more\n", len(caseClashes)-i)
+				break
+			}
+			fmt.Fprintf(os.Stderr, "  %s: %s\n", c.path, c.message)
+		}
+	}
+	// Whatever the path mapping did, nothing may be written outside destDir
+	for _, p := range plans {
+		if !isWithinRoot(destDir, p[1]) {
+			tui.Close()
+			fail(fmt.Errorf("refusing to write outside the destination folder %s: %s", destDir, p[1]))
+		}
+	}
+
+	if *verifyOnly {
+		vs := verifyPlans(ctx, plans, *workers, limits, tui)
+		// leave the alt screen first so the report stays visible
+		tui.Close()
+		printVerifyStats(vs)
+		if jsonOut != nil {
+			writeJSONSummary(jsonOut, runSummary{Verify: &verifySummary{
+				Checked: vs.checked, OK: vs.ok, Missing: vs.missing, Unchecked: vs.unchecked, SizeOnly: vs.sizeOnly, Mismatches: summaryFailures(vs.mismatches),
+			}})
+		}
+		if code := vs.exitCode(); code != 0 {
+			os.Exit(code)
+		}
+		return
+	}
 
 	// Filter existing same-size
-	toCopy := make([][2]string, 0, len(plans))
-	skippedExisting := 0
-	for _, p := range plans {
-		src, dst := p[0], p[1]
-		if st, err := os.Stat(dst); err == nil {
-			if st.Mode().IsRegular() {
-				if sst, err2 := os.Stat(src); err2 == nil && sst.Size() == st.Size() {
-					skippedExisting++
-					continue
-				}
-			}
+	existing := filterExisting(plans, *keepBoth, *syncTimes)
+	toCopy, skippedExisting := existing.toCopy, existing.skipped
+	if *maxFiles > 0 || maxBytes > 0 {
+		if n := limitRun(toCopy, *maxFiles, maxBytes); n < len(toCopy) {
+			fmt.Fprintf(stdout, "Run limit reached: copying %d of %d files now, the rest is left for a later --resume run\n", n, len(toCopy))
+			toCopy = toCopy[:n]
 		}
-		toCopy = append(toCopy, p)
 	}
 
 	var toCopyBytes int64
@@ -244,8 +498,18 @@ func main() {
 			toCopyBytes += st.Size()
 		}
 	}
-	fmt.Printf("Already present (same size): %d files\n", skippedExisting)
-	fmt.Printf("To copy now: %d files, %s\n", len(toCopy), humanSize(toCopyBytes))
+	var links []hardlink
+	if *preserveHardlinks {
+		toCopy, links = splitHardlinks(toCopy)
+	}
+	fmt.Fprintf(stdout, "Already present (same size): %d files\n", skippedExisting)
+	if len(existing.timeFixes) > 0 {
+		fmt.Fprintf(stdout, "Modification time differs on %d of them\n", len(existing.timeFixes))
+	}
+	fmt.Fprintf(stdout, "To copy now: %d files, %s\n", len(toCopy)+len(links), humanSize(toCopyBytes))
+	if len(links) > 0 {
+		fmt.Fprintf(stdout, "Hard links to create (linked or duplicate files): %d\n", len(links))
+	}
 
 	manifestPath := filepath.Join(destDir, "backup-manifest.jsonl")
 	if *dryRun {
@@ -263,10 +527,32 @@ func main() {
 		if len(list) > 5 {
 			list = list[:5]
 		}
-		fmt.Printf("Plan by priority (top 5): %v\n", list)
-		fmt.Println("Dry run complete. No files were copied.")
+		fmt.Fprintf(stdout, "Plan by priority (top 5): %v\n", list)
+		fmt.Fprintln(stdout, "Dry run complete. No files were copied.")
+		if jsonOut != nil {
+			writeJSONSummary(jsonOut, runSummary{Plan: &planSummary{
+				Files: len(toCopy) + len(links), Bytes: toCopyBytes, AlreadyPresent: skippedExisting, Links: len(links), CaseClashes: len(caseClashes),
+			}})
+		}
 		return
 	}
+	if n := applyTimeFixes(existing.timeFixes); n > 0 {
+		fmt.Fprintf(stdout, "Modification time updated on %d files already present\n", n)
+	}
+	// Hashing reads every same-size file in full, so it waits until a copy is certain
+	if *dedupe {
+		if err := hardLinkProbe(destDir); err != nil {
+			fmt.Fprintf(os.Stderr, "warning: --dedupe ignored, the destination cannot hold hard links: %v\n", err)
+		} else {
+			if tui != nil {
+				tui.AppendLog("Hashing same-size files to find duplicates...")
+			}
+			var dups []hardlink
+			toCopy, dups = splitDuplicates(ctx, toCopy)
+			links = append(links, dups...)
+			fmt.Fprintf(stdout, "Duplicates to hard-link instead of copy: %d\n", len(dups))
+		}
+	}
 
 	// Copy concurrently
 	w := *workers
@@ -276,10 +562,58 @@ func main() {
 	if w < 1 {
 		w = 1
 	}
-	fmt.Printf("Starting copy with %d worker(s)...\n", w)
+	fmt.Fprintf(stdout, "Starting copy with %d worker(s)...\n", w)
 	start := time.Now()
-	copied, errorsN := copyAll(ctx, toCopy, manifestPath, w, tui)
-	fmt.Printf("Copy complete in %.2fs: copied=%d, skipped=%d, errors=%d\n", time.Since(start).Seconds(), copied, skippedExisting, errorsN)
+	stats := copyAll(ctx, toCopy, links, manifestPath, report, w, *failFast, tui)
+	if report != nil {
+		report.Flush()
+		if err := report.Error(); err != nil {
+			fmt.Fprintf(os.Stderr, "warning: failed to write CSV report: %v\n", err)
+		}
+	}
+	stats.addSkipped(skipExists, skippedExisting)
+	for reason, n := range scan.filtered {
+		stats.addSkipped(reason, n)
+	}
+	// left out before the copy, but missing from the backup all the same
+	stats.errors += len(caseClashes)
+	stats.failures = append(stats.failures, caseClashes...)
+	stats.elapsed = time.Since(start)
+	printStats(stats)
+	if jsonOut != nil {
+		writeJSONSummary(jsonOut, runSummary{
+			Copied: stats.copied, Skipped: stats.skipped, SkipReasons: stats.skipReasons, Errors: stats.errors, NotAttempted: stats.notAttempted,
+			Bytes: stats.bytes, BytesWritten: stats.written(), ElapsedSeconds: stats.elapsed.Seconds(), Failures: summaryFailures(stats.failures),
+		})
+	}
+	// [src, dst] directory pairs whose timestamps are restored below
+	dirPairs := make([][2]string, 0, len(plans))
+	for _, p := range plans {
+		dirPairs = append(dirPairs, [2]string{filepath.Dir(p[0]), filepath.Dir(p[1])})
+	}
+	if *keepEmptyDirs && !*flatten {
+		for _, d := range scan.emptyDirs {
+			dst := filepath.Join(destDir, relativeDestPath(d, sources, rootNames))
+			if !isWithinRoot(destDir, dst) {
+				continue
+			}
+			if err := os.MkdirAll(dst, 0o755); err != nil {
+				fmt.Fprintf(os.Stderr, "warning: failed to create empty directory %s: %v\n", dst, err)
+				continue
+			}
+			dirPairs = append(dirPairs, [2]string{d, dst})
+		}
+	}
+	if !*noDirTimes {
+		restoreDirTimes(dirPairs, destDir)
+	}
+	if stats.errors > 0 || stats.notAttempted > 0 {
+		tui.Close()
+		if *failFast {
+			fmt.Fprintf(os.Stderr, "copy stopped after first error (--fail-fast), see %s\n", manifestPath)
+		}
+		os.Exit(exitCopyErrors)
+	}
 }
 
 func defaultHome() string {
@@ -305,6 +639,45 @@ func usableFreeSpace(path string, reserve int64) int64 {
 	return getUnixFreeSpace(path, reserve)
 }
 
+// validateSubdir rejects destination folder names that Windows or a FAT/exFAT
+// stick cannot store, so the run fails up front instead of on every file.
+func validateSubdir(p string) error {
+	parts := strings.FieldsFunc(p, func(r rune) bool { return r == '/' || r == '\\' })
+	for _, part := range parts {
+		if part == "." {
+			continue
+		}
+		if strings.ContainsAny(part, `<>:"|?*`) {
+			return fmt.Errorf("invalid destination subdirectory %q: %q contains one of <>:\"|?*", p, part)
+		}
+		for _, r := range part {
+			if r < 32 {
+				return fmt.Errorf("invalid destination subdirectory %q: %q contains a control character", p, part)
+			}
+		}
+		if strings.HasSuffix(part, " ") || strings.HasSuffix(part, ".") {
+			return fmt.Errorf("invalid destination subdirectory %q: %q ends with a space or dot", p, part)
+		}
+		// Device names are reserved with any extension too (NUL.txt, com1.log)
+		stem, _, _ := strings.Cut(part, ".")
+		if isReservedName(strings.ToUpper(strings.TrimSpace(stem))) {
+			return fmt.Errorf("invalid destination subdirectory %q: %q is a reserved device name on Windows", p, part)
+		}
+	}
+	return nil
+}
+
+func isReservedName(upper string) bool {
+	switch upper {
+	case "CON", "PRN", "AUX", "NUL":
+		return true
+	}
+	if len(upper) == 4 && (strings.HasPrefix(upper, "COM") || strings.HasPrefix(upper, "LPT")) {
+		return upper[3] >= '1' && upper[3] <= '9'
+	}
+	return false
+}
+
 func loadImportanceProfile(path string) ([]Tier, error) {
 	f, err := os.Open(path)
 	if err != nil {
@@ -359,12 +732,69 @@ func humanSize(n int64) string {
 	return fmt.Sprintf("%.2f %s", x, units[i])
 }
 
-func scanSources(ctx context.Context, sources []string, tiers []Tier, excludes []string, autoExcludeRoot string, tui *TUI) []FileInfoRec {
+// scanOptions holds the optional per-file filters applied while scanning.
+type scanOptions struct {
+	minSize, maxSize     int64     // bytes, 0 = no limit
+	newerThan, olderThan time.Time // zero = no limit
+	skipHidden           bool      // hidden directories are not descended into
+	rules                []excludeRule
+	maxDepth             int    // folder levels below a source to descend into, -1 = no limit
+	marker               string // folders containing a file with this name are skipped, with their subtree
+}
+
+// filterReason is the skip reason for a file left out by the size or date
+// filters, or "" when it is kept.
+func (o scanOptions) filterReason(info fs.FileInfo) string {
+	if o.minSize > 0 && info.Size() < o.minSize {
+		return skipSizeFilter
+	}
+	if o.maxSize > 0 && info.Size() > o.maxSize {
+		return skipSizeFilter
+	}
+	if !o.newerThan.IsZero() && !info.ModTime().After(o.newerThan) {
+		return skipDateFilter
+	}
+	if !o.olderThan.IsZero() && !info.ModTime().Before(o.olderThan) {
+		return skipDateFilter
+	}
+	return ""
+}
+
+// scanResult is what scanSources found: the candidate files plus any source
+// directories that have no entries at all.
+type scanResult struct {
+	files      []FileInfoRec
+	emptyDirs  []string
+	unreadable []copyFailure   // sources and folders that could not be listed; the rest is still backed up
+	filtered   map[string]int // files left out by the size and date filters, per reason
+}
+
+// hasEntry reports whether entries hold a regular file called exactly name.
+// A folder or a differently cased name is not an --exclude-marker.
+func hasEntry(entries []fs.DirEntry, name string) bool {
+	for _, e := range entries {
+		if e.Name() == name && e.Type().IsRegular() {
+			return true
+		}
+	}
+	return false
+}
+
+// pathErrMessage drops the operation and path from a *fs.PathError when the
+// path is printed next to it anyway.
+func pathErrMessage(err error) string {
+	if pe, ok := err.(*fs.PathError); ok {
+		return pe.Err.Error()
+	}
+	return err.Error()
+}
+
+func scanSources(ctx context.Context, sources []string, tiers []Tier, excludes []string, opts scanOptions, autoExcludeRoot string, tui *TUI) scanResult {
 	if len(tiers) == 0 {
 		tiers = defaultProfile()
 	}
 	autoExcludeRoot, _ = filepath.Abs(autoExcludeRoot)
-	var out []FileInfoRec
+	var out scanResult
 	lowers := lowerAll(excludes)
 	// progress counters for scan
 	var scanned int64
@@ -379,22 +809,45 @@ func scanSources(ctx context.Context, sources []string, tiers []Tier, excludes [
 		default:
 		}
 		src = expandPath(src)
-		if st, err := os.Stat(src); err != nil || !st.IsDir() {
+		if st, err := os.Stat(src); err != nil {
+			out.unreadable = append(out.unreadable, copyFailure{path: src, message: pathErrMessage(err)})
+			continue
+		} else if !st.IsDir() {
+			out.unreadable = append(out.unreadable, copyFailure{path: src, message: "not a directory"})
 			continue
 		}
+		// A source that is itself a symlink or junction is followed (links below
+		// it are not). Files keep paths under the name given, so destinations do
+		// not depend on where the link points; the USB check uses the real target.
 		absSrc, _ := filepath.Abs(src)
-		if prefixOf(absSrc, autoExcludeRoot) {
-			fmt.Printf("Auto-excluded (USB): %s\n", src)
+		realSrc := absSrc
+		if r, err := filepath.EvalSymlinks(absSrc); err == nil {
+			realSrc = r
+		}
+		if prefixOf(absSrc, autoExcludeRoot) || prefixOf(realSrc, autoExcludeRoot) {
+			fmt.Fprintf(stdout, "Auto-excluded (USB): %s\n", src)
 			continue
 		}
+		// depth[d] is how many levels d is below absSrc
+		depth := map[string]int{absSrc: 0}
 		stack := []string{absSrc}
 		for len(stack) > 0 {
 			cur := stack[len(stack)-1]
 			stack = stack[:len(stack)-1]
 			entries, err := os.ReadDir(cur)
 			if err != nil {
+				out.unreadable = append(out.unreadable, copyFailure{path: cur, message: pathErrMessage(err)})
 				continue
 			}
+			if opts.marker != "" && hasEntry(entries, opts.marker) {
+				if tui != nil {
+					tui.AppendLog(fmt.Sprintf("Skipped (%s): %s", opts.marker, cur))
+				}
+				continue
+			}
+			if len(entries) == 0 {
+				out.emptyDirs = append(out.emptyDirs, cur)
+			}
 			for _, e := range entries {
 				select {
 				case <-ctx.Done():
@@ -406,6 +859,10 @@ func scanSources(ctx context.Context, sources []string, tiers []Tier, excludes [
 				}
 				name := e.Name()
 				full := filepath.Join(cur, name)
+				// isHidden is implemented in hidden_unix.go and hidden_windows.go
+				if opts.skipHidden && isHidden(name, e) {
+					continue
+				}
 				if e.IsDir() {
 					if _, skip := excludedDirNames[name]; skip {
 						continue
@@ -413,6 +870,14 @@ func scanSources(ctx context.Context, sources []string, tiers []Tier, excludes [
 					if matchAny(full, excludes) {
 						continue
 					}
+					if excludedBy(opts.rules, absSrc, full, true) {
+						continue
+					}
+					if opts.maxDepth >= 0 && depth[cur] >= opts.maxDepth {
+						// beyond --max-depth; not an empty folder, so --keep-empty-dirs leaves it out too
+						continue
+					}
+					depth[full] = depth[cur] + 1
 					stack = append(stack, full)
 				} else {
 					if (e.Type() & fs.ModeSymlink) != 0 {
@@ -428,8 +893,18 @@ func scanSources(ctx context.Context, sources []string, tiers []Tier, excludes [
 					if matchAny(strings.ToLower(full), lowers) {
 						continue
 					}
+					if excludedBy(opts.rules, absSrc, full, false) {
+						continue
+					}
+					if reason := opts.filterReason(info); reason != "" {
+						if out.filtered == nil {
+							out.filtered = map[string]int{}
+						}
+						out.filtered[reason]++
+						continue
+					}
 					pr := priorityFor(full, tiers)
-					out = append(out, FileInfoRec{Path: full, Size: info.Size(), MTime: info.ModTime(), Priority: pr})
+					out.files = append(out.files, FileInfoRec{Path: full, Size: info.Size(), MTime: info.ModTime(), Priority: pr})
 					scanned++
 					if tui != nil && time.Since(lastReport) > 500*time.Millisecond {
 						tui.AppendLog(fmt.Sprintf("Scanning: %d files found...", scanned))
@@ -442,6 +917,57 @@ func scanSources(ctx context.Context, sources []string, tiers []Tier, excludes [
 	return out
 }
 
+// parseSize parses sizes such as "4096", "512K", "10MB" or "1.5GiB".
+// Units are binary (1 KB = 1024 B) to match humanSize.
+func parseSize(s string) (int64, error) {
+	str := strings.ToUpper(strings.TrimSpace(s))
+	// "KiB" style units: the I is only valid between a unit letter and B
+	if rest, ok := strings.CutSuffix(str, "IB"); ok && rest != "" && strings.ContainsRune("KMGT", rune(rest[len(rest)-1])) {
+		str = rest
+	} else {
+		str = strings.TrimSuffix(str, "B")
+	}
+	mult := int64(1)
+	if n := len(str); n > 0 {
+		switch str[n-1] {
+		case 'K':
+			mult = 1 << 10
+		case 'M':
+			mult = 1 << 20
+		case 'G':
+			mult = 1 << 30
+		case 'T':
+			mult = 1 << 40
+		}
+		if mult > 1 {
+			str = str[:n-1]
+		}
+	}
+	v, err := strconv.ParseFloat(strings.TrimSpace(str), 64)
+	if err != nil || v < 0 || math.IsInf(v, 0) || math.IsNaN(v) {
+		return 0, fmt.Errorf("invalid size %q (want e.g. 4096, 512KB, 10MB)", s)
+	}
+	return int64(v * float64(mult)), nil
+}
+
+// parseCutoff turns a date ("2024-01-31", local time) or an age relative to now
+// ("36h", "30d") into an absolute point in time.
+func parseCutoff(s string, now time.Time) (time.Time, error) {
+	s = strings.TrimSpace(s)
+	if t, err := time.ParseInLocation("2006-01-02", s, time.Local); err == nil {
+		return t, nil
+	}
+	if days, ok := strings.CutSuffix(s, "d"); ok {
+		if n, err := strconv.Atoi(days); err == nil && n >= 0 {
+			return now.AddDate(0, 0, -n), nil
+		}
+	}
+	if d, err := time.ParseDuration(s); err == nil && d >= 0 {
+		return now.Add(-d), nil
+	}
+	return time.Time{}, fmt.Errorf("invalid time %q (want YYYY-MM-DD or an age like 36h or 30d)", s)
+}
+
 func lowerAll(in []string) []string {
 	out := make([]string, len(in))
 	for i, s := range in {
@@ -480,9 +1006,8 @@ func priorityFor(path string, tiers []Tier) int {
 func selectFiles(files []FileInfoRec, capacity int64, objective string) ([]FileInfoRec, int64) {
 	byPr := map[int][]FileInfoRec{}
 	for _, f := range files {
-		if f.Size > 0 {
-			byPr[f.Priority] = append(byPr[f.Priority], f)
-		}
+		// empty files cost no space, so they are always selected
+		byPr[f.Priority] = append(byPr[f.Priority], f)
 	}
 	var selected []FileInfoRec
 	var used int64
@@ -493,11 +1018,18 @@ func selectFiles(files []FileInfoRec, capacity int64, objective string) ([]FileI
 	sort.Slice(prs, func(i, j int) bool { return prs[i] > prs[j] })
 	for _, pr := range prs {
 		items := byPr[pr]
-		if objective == "count" {
-			sort.Slice(items, func(i, j int) bool { return items[i].Size < items[j].Size })
-		} else {
-			sort.Slice(items, func(i, j int) bool { return items[i].Size > items[j].Size })
-		}
+		// sort.Slice is not stable, so equal sizes are ordered by path
+		// (byte-wise); the same tree then always yields the same selection
+		// and copy order
+		sort.Slice(items, func(i, j int) bool {
+			if items[i].Size != items[j].Size {
+				if objective == "count" {
+					return items[i].Size < items[j].Size
+				}
+				return items[i].Size > items[j].Size
+			}
+			return items[i].Path < items[j].Path
+		})
 		for _, f := range items {
 			if used+f.Size <= capacity {
 				selected = append(selected, f)
@@ -508,7 +1040,11 @@ func selectFiles(files []FileInfoRec, capacity int64, objective string) ([]FileI
 	return selected, used
 }
 
-func relativeDestPath(src string, bases []string) string {
+// relativeDestPath returns src relative to the deepest matching base. With
+// rootNames (see sourceFolderNames) the base's folder name is kept as the first
+// path element, so several sources land in separate folders rather than one
+// merged tree.
+func relativeDestPath(src string, bases []string, rootNames map[string]string) string {
 	srcAbs, _ := filepath.Abs(src)
 	best := ""
 	for _, b := range bases {
@@ -524,9 +1060,197 @@ func relativeDestPath(src string, bases []string) string {
 	if err != nil || strings.HasPrefix(rel, "..") {
 		return filepath.Base(srcAbs)
 	}
+	if rootNames != nil {
+		return filepath.Join(rootNames[best], rel)
+	}
 	return rel
 }
 
+// sourceFolderNames maps each absolute base to its --source-folders folder: its
+// own name, or "name (2)", "name (3)", ... for later bases whose name is already
+// taken (/a/Docs and /b/Docs), ignoring case as FAT/exFAT does. Names follow the
+// order the sources are given in.
+func sourceFolderNames(bases []string) map[string]string {
+	names := make(map[string]string, len(bases))
+	taken := map[string]bool{}
+	for _, b := range bases {
+		bAbs, _ := filepath.Abs(expandPath(b))
+		if _, ok := names[bAbs]; ok {
+			continue
+		}
+		name := filepath.Base(bAbs)
+		for n := 2; taken[strings.ToLower(name)]; n++ {
+			name = fmt.Sprintf("%s (%d)", filepath.Base(bAbs), n)
+		}
+		taken[strings.ToLower(name)] = true
+		names[bAbs] = name
+	}
+	return names
+}
+
+// flattenPlans points every plan at destDir/<file name>. Names are handed out in
+// source path order, so repeated runs give the same file the same "name (n).ext".
+func flattenPlans(plans [][2]string, destDir string) {
+	order := make([]int, len(plans))
+	for i := range order {
+		order[i] = i
+	}
+	sort.Slice(order, func(a, b int) bool { return plans[order[a]][0] < plans[order[b]][0] })
+	taken := make(map[string]struct{}, len(plans))
+	for _, i := range order {
+		dst := numberedName(filepath.Join(destDir, filepath.Base(plans[i][0])), func(p string) bool {
+			_, used := taken[p]
+			return !used
+		})
+		taken[dst] = struct{}{}
+		plans[i][1] = dst
+	}
+}
+
+// dropDuplicateDestinations removes plans repeated verbatim (a file reached
+// through two overlapping sources) and returns the rest along with "dst <- src1,
+// src2" descriptions of destinations claimed by different source files, which
+// are kept in the result.
+func dropDuplicateDestinations(plans [][2]string) ([][2]string, []string) {
+	first := make(map[string]string, len(plans)) // dst -> first src
+	out := plans[:0]
+	var clashes []string
+	for _, p := range plans {
+		if src, ok := first[p[1]]; ok {
+			if src == p[0] {
+				continue
+			}
+			clashes = append(clashes, p[1]+" <- "+src+", "+p[0])
+		} else {
+			first[p[1]] = p[0]
+		}
+		out = append(out, p)
+	}
+	return out, clashes
+}
+
+// resolveCaseCollisions finds plans whose destination differs from another one
+// only by letter case (File.txt vs file.txt). FAT/exFAT sticks and Windows treat
+// those as the same file, so one would silently replace the other. Plans are
+// visited in source path order so reruns pick the same file as the later one.
+// With rename the later plan gets the first "name (n).ext" that no other plan
+// and no differently-cased entry on the destination uses, and a "src -> new dst"
+// description is returned for it; otherwise it is left out of the returned
+// plans and reported as a failure.
+func resolveCaseCollisions(plans [][2]string, rename bool) ([][2]string, []string, []copyFailure) {
+	order := make([]int, len(plans))
+	for i := range order {
+		order[i] = i
+	}
+	sort.Slice(order, func(a, b int) bool { return plans[order[a]][0] < plans[order[b]][0] })
+	onDisk := map[string]map[string]string{} // dst dir -> lower-cased entry name -> name
+	// clashesOnDisk reports an existing entry that matches p only when case is
+	// ignored; one with the exact name is this plan's copy from an earlier run
+	clashesOnDisk := func(p string) bool {
+		dir, base := filepath.Split(p)
+		names, ok := onDisk[dir]
+		if !ok {
+			names = map[string]string{}
+			entries, _ := os.ReadDir(dir)
+			for _, e := range entries {
+				names[strings.ToLower(e.Name())] = e.Name()
+			}
+			onDisk[dir] = names
+		}
+		name, ok := names[strings.ToLower(base)]
+		return ok && name != base
+	}
+	seen := make(map[string]string, len(plans)) // lower-cased dst -> dst
+	drop := map[int]bool{}
+	var renamed []string
+	var clashes []copyFailure
+	for _, i := range order {
+		dst := plans[i][1]
+		if prev, ok := seen[strings.ToLower(dst)]; ok && prev != dst {
+			if !rename {
+				drop[i] = true
+				clashes = append(clashes, copyFailure{path: plans[i][0], message: "name differs only by letter case from " + prev})
+				continue
+			}
+			dst = numberedName(dst, func(p string) bool {
+				_, used := seen[strings.ToLower(p)]
+				return !used && !clashesOnDisk(p)
+			})
+			renamed = append(renamed, plans[i][0]+" -> "+dst)
+			plans[i][1] = dst
+		}
+		seen[strings.ToLower(dst)] = dst
+	}
+	if len(drop) == 0 {
+		return plans, renamed, nil
+	}
+	out := plans[:0]
+	for i, p := range plans {
+		if !drop[i] {
+			out = append(out, p)
+		}
+	}
+	return out, renamed, clashes
+}
+
+// availableName returns path if it is free, otherwise the first "name (n).ext"
+// variant that neither exists on disk nor is already claimed in taken.
+func availableName(path string, taken map[string]struct{}) string {
+	return numberedName(path, func(p string) bool {
+		if _, ok := taken[p]; ok {
+			return false
+		}
+		_, err := os.Lstat(p)
+		return os.IsNotExist(err)
+	})
+}
+
+// numberedName returns path if free reports it usable, otherwise the first
+// "name (n).ext" variant that is.
+func numberedName(path string, free func(string) bool) string {
+	if free(path) {
+		return path
+	}
+	for n := 1; ; n++ {
+		cand := numberedVariant(path, n)
+		if free(cand) {
+			return cand
+		}
+	}
+}
+
+// numberedVariant returns the "name (n).ext" form of path.
+func numberedVariant(path string, n int) string {
+	dir, base := filepath.Split(path)
+	ext := filepath.Ext(base)
+	stem := strings.TrimSuffix(base, ext)
+	if stem == "" {
+		// dotfiles such as ".bashrc" have no extension to preserve
+		stem, ext = base, ""
+	}
+	return filepath.Join(dir, fmt.Sprintf("%s (%d)%s", stem, n, ext))
+}
+
+// existingVariants returns the "name (n).ext" variants of path that exist on
+// disk, from (1) up to the first missing one.
+func existingVariants(path string) []string {
+	var out []string
+	for n := 1; ; n++ {
+		v := numberedVariant(path, n)
+		if _, err := os.Lstat(v); err != nil {
+			return out
+		}
+		out = append(out, v)
+	}
+}
+
+// isWithinRoot reports whether p is strictly below root once ".." elements are
+// resolved, i.e. safe to write to for a backup into root.
+func isWithinRoot(root, p string) bool {
+	r, p := filepath.Clean(root), filepath.Clean(p)
+	return r != p && prefixOf(p, r)
+}
+
 func prefixOf(path, base string) bool {
 	if path == base {
 		return true
@@ -545,12 +1269,289 @@ func prefixOf(path, base string) bool {
 	return false
 }
 
-func copyAll(ctx context.Context, pairs [][2]string, manifestPath string, workers int, tui *TUI) (int, int) {
+// fileKey identifies a file independent of its path (device/volume + inode/file index).
+type fileKey struct{ dev, ino uint64 }
+
+// hardlink is a planned destination that should become another name for the
+// already copied target, mirroring a hard link between source files.
+type hardlink struct {
+	src, target, dst string
+}
+
+// splitHardlinks moves pairs whose source is a hard link to an earlier source
+// out of the copy list; they are recreated as links to that earlier destination.
+// fileID is implemented in hardlink_unix.go and hardlink_windows.go.
+func splitHardlinks(pairs [][2]string) ([][2]string, []hardlink) {
+	first := map[fileKey]string{}
+	copies := make([][2]string, 0, len(pairs))
+	var links []hardlink
+	for _, p := range pairs {
+		if fi, err := os.Stat(p[0]); err == nil {
+			if id, ok := fileID(p[0], fi); ok {
+				if target, seen := first[id]; seen {
+					links = append(links, hardlink{src: p[0], target: target, dst: p[1]})
+					continue
+				}
+				first[id] = p[1]
+			}
+		}
+		copies = append(copies, p)
+	}
+	return copies, links
+}
+
+// splitDuplicates moves pairs whose source has the same content as an earlier
+// pair's source out of the copy list, as links to that pair's destination.
+// Only files that share their size with another file are hashed; once ctx is
+// cancelled hashing stops and the remaining files are simply copied.
+func splitDuplicates(ctx context.Context, pairs [][2]string) ([][2]string, []hardlink) {
+	bySize := map[int64][]int{}
+	for i, p := range pairs {
+		if st, err := os.Stat(p[0]); err == nil {
+			bySize[st.Size()] = append(bySize[st.Size()], i)
+		}
+	}
+	dupOf := map[int]int{} // pair index -> index of the first pair with that content
+	for _, idx := range bySize {
+		if len(idx) < 2 {
+			continue
+		}
+		first := map[[sha256.Size]byte]int{}
+		for _, i := range idx {
+			if ctx.Err() != nil {
+				break
+			}
+			sum, err := fileSHA256(pairs[i][0])
+			if err != nil {
+				continue
+			}
+			if j, seen := first[sum]; seen {
+				dupOf[i] = j
+				continue
+			}
+			first[sum] = i
+		}
+	}
+	copies := make([][2]string, 0, len(pairs)-len(dupOf))
+	var links []hardlink
+	for i, p := range pairs {
+		if j, dup := dupOf[i]; dup {
+			links = append(links, hardlink{src: p[0], target: pairs[j][1], dst: p[1]})
+			continue
+		}
+		copies = append(copies, p)
+	}
+	return copies, links
+}
+
+// hardLinkProbe creates and removes a hard link in dir, which fails on
+// filesystems without hard links such as FAT/exFAT.
+func hardLinkProbe(dir string) error {
+	f, err := os.CreateTemp(dir, ".backup-linkprobe-*")
+	if err != nil {
+		return err
+	}
+	name := f.Name()
+	f.Close()
+	defer os.Remove(name)
+	if err := os.Link(name, name+".link"); err != nil {
+		return err
+	}
+	return os.Remove(name + ".link")
+}
+
+// destFoldsCase reports whether dir treats names that differ only by letter case
+// as the same file, by looking up a temporary file under its upper-case name.
+// When the probe cannot be made it assumes so, which is the safe side.
+func destFoldsCase(dir string) bool {
+	f, err := os.CreateTemp(dir, ".backup-caseprobe-*")
+	if err != nil {
+		return true
+	}
+	name := f.Name()
+	f.Close()
+	defer os.Remove(name)
+	st, err := os.Stat(name)
+	if err != nil {
+		return true
+	}
+	other, err := os.Stat(filepath.Join(dir, strings.ToUpper(filepath.Base(name))))
+	return err == nil && os.SameFile(st, other)
+}
+
+func fileSHA256(path string) ([sha256.Size]byte, error) {
+	var sum [sha256.Size]byte
+	f, err := os.Open(path)
+	if err != nil {
+		return sum, err
+	}
+	defer f.Close()
+	h := sha256.New()
+	if _, err := io.Copy(h, f); err != nil {
+		return sum, err
+	}
+	copy(sum[:], h.Sum(nil))
+	return sum, nil
+}
+
+// linkOrCopy creates l.dst as a hard link to the copied target. When the target
+// is missing or the destination filesystem has no hard links (FAT/exFAT), it
+// falls back to a regular copy.
+func linkOrCopy(ctx context.Context, l hardlink, agg *progressAgg, mu *sync.Mutex, logsCh chan string, interactive bool) (string, string, int) {
+	if err := os.MkdirAll(filepath.Dir(l.dst), 0o755); err != nil {
+		return "error", mkdirErrMessage(filepath.Dir(l.dst), err), errCode(err)
+	}
+	if _, err := os.Stat(l.target); err == nil {
+		_ = os.Remove(l.dst)
+		if err := os.Link(l.target, l.dst); err == nil {
+			if st, err := os.Stat(l.src); err == nil && agg != nil {
+				agg.Add(st.Size())
+			}
+			return "linked", "hardlink to " + l.target, 0
+		}
+	}
+	return copyOneWithProgress(ctx, l.src, l.dst, agg, mu, logsCh, interactive)
+}
+
+// copyStats summarises a copy run; copyAll fills everything except elapsed.
+type copyStats struct {
+	copied       int // includes recreated hard links
+	skipped      int
+	skipReasons  map[string]int // skipped files per reason (the manifest message)
+	errors       int            // includes copies cut short by an interrupt
+	notAttempted int            // left unprocessed (or cut short) after an interrupt or --fail-fast stop
+	bytes        int64          // logical: sizes of copied and linked files (progress bytes)
+	linkedBytes  int64          // size of files stored as hard links instead of copies
+	elapsed      time.Duration
+	failures     []copyFailure
+}
+
+// written is what was physically written to the destination.
+func (s copyStats) written() int64 { return s.bytes - s.linkedBytes }
+
+// addSkipped counts n files skipped outside copyAll under reason.
+func (s *copyStats) addSkipped(reason string, n int) {
+	if n == 0 {
+		return
+	}
+	s.skipped += n
+	s.skipReasons[reason] += n
+}
+
+type copyFailure struct {
+	path    string
+	message string
+}
+
+// Reasons recorded for skipped files, in the manifest and the final summary.
+const (
+	skipExists     = "exists-same-size"
+	skipInUse      = "in use by another process"
+	skipRemoved    = "source removed after planning"
+	skipSizeFilter = "outside the size filter"
+	skipDateFilter = "outside the date filter"
+)
+
+// manifestFlushInterval is how often copyAll writes buffered manifest records to disk.
+const manifestFlushInterval = 10 * time.Second
+
+// maxListedFailures caps the failed files printed at the end; the manifest has all of them.
+const maxListedFailures = 20
+
+func printStats(s copyStats) {
+	fmt.Fprintf(stdout, "Copy complete in %.2fs: copied=%d, skipped=%d, errors=%d (%s transferred)\n", s.elapsed.Seconds(), s.copied, s.skipped, s.errors, humanSize(s.written()))
+	if s.notAttempted > 0 {
+		fmt.Fprintf(stdout, "  not attempted after the copy stopped: %d (picked up by a later --resume run)\n", s.notAttempted)
+	}
+	if s.linkedBytes > 0 {
+		fmt.Fprintf(stdout, "Backed up %s of files; hard links saved %s of space on the destination\n", humanSize(s.bytes), humanSize(s.linkedBytes))
+	}
+	reasons := make([]string, 0, len(s.skipReasons))
+	for r := range s.skipReasons {
+		reasons = append(reasons, r)
+	}
+	sort.Strings(reasons)
+	for _, r := range reasons {
+		fmt.Fprintf(stdout, "  skipped (%s): %d\n", r, s.skipReasons[r])
+	}
+	for i, f := range s.failures {
+		if i == maxListedFailures {
+			fmt.Fprintf(stdout, "  ... and %d more, see the manifest\n", len(s.failures)-i)
+			break
+		}
+		fmt.Fprintf(stdout, "  failed: %s: %s\n", f.path, f.message)
+	}
+}
+
+// reportHeader names the --report-csv columns: one row per file copied, linked,
+// skipped, failed or not attempted during the copy (the same records as the manifest).
+var reportHeader = []string{"source", "destination", "size", "status", "message", "code"}
+
+// runSummary is the --json report. Verify is set (and the copy fields zero)
+// for --verify-only runs, Plan for --dry-run runs.
+type runSummary struct {
+	Copied         int              `json:"copied"`
+	Skipped        int              `json:"skipped"`
+	SkipReasons    map[string]int   `json:"skip_reasons,omitempty"`
+	Errors         int              `json:"errors"`
+	NotAttempted   int              `json:"not_attempted"`
+	Bytes          int64            `json:"bytes"`
+	BytesWritten   int64            `json:"bytes_written"` // less than bytes when files were hard-linked
+	ElapsedSeconds float64          `json:"elapsed_seconds"`
+	Failures       []summaryFailure `json:"failures"`
+	Verify         *verifySummary   `json:"verify,omitempty"`
+	Plan           *planSummary     `json:"plan,omitempty"`
+}
+
+// planSummary is what a --dry-run would have copied.
+type planSummary struct {
+	Files          int   `json:"files"` // including hard links to create
+	Bytes          int64 `json:"bytes"`
+	AlreadyPresent int   `json:"already_present"`
+	Links          int   `json:"links"`
+	CaseClashes    int   `json:"case_clashes"` // left out, see --rename-case-clashes
+}
+
+type verifySummary struct {
+	Checked    int              `json:"checked"`
+	OK         int              `json:"ok"`
+	Missing    int              `json:"missing"`
+	Unchecked  int              `json:"unchecked"`
+	SizeOnly   int              `json:"size_only"`
+	Mismatches []summaryFailure `json:"mismatches"`
+}
+
+type summaryFailure struct {
+	Path    string `json:"path"`
+	Message string `json:"message"`
+}
+
+func summaryFailures(list []copyFailure) []summaryFailure {
+	out := make([]summaryFailure, 0, len(list))
+	for _, f := range list {
+		out = append(out, summaryFailure{Path: f.path, Message: f.message})
+	}
+	return out
+}
+
+func writeJSONSummary(w io.Writer, s runSummary) {
+	enc := json.NewEncoder(w)
+	enc.SetIndent("", "  ")
+	if err := enc.Encode(s); err != nil {
+		fmt.Fprintf(os.Stderr, "warning: failed to write JSON summary: %v\n", err)
+	}
+}
+
+// copyAll copies pairs and then links, recording every file in the manifest and,
+// when report is not nil, as a CSV row (see reportHeader).
+func copyAll(ctx context.Context, pairs [][2]string, links []hardlink, manifestPath string, report *csv.Writer, workers int, failFast bool, tui *TUI) copyStats {
+	// stop is used by --fail-fast to halt the remaining copies after an error
+	ctx, stop := context.WithCancel(ctx)
+	defer stop()
 	jobs := make(chan [2]string, workers*2)
 	var wg sync.WaitGroup
 	var mu sync.Mutex
-	copied := 0
-	errorsN := 0
+	stats := copyStats{skipReasons: map[string]int{}}
 	// Compute total bytes to copy
 	var totalBytes int64
 	for _, p := range pairs {
@@ -558,8 +1559,13 @@ func copyAll(ctx context.Context, pairs [][2]string, manifestPath string, worker
 			totalBytes += st.Size()
 		}
 	}
+	for _, l := range links {
+		if st, err := os.Stat(l.src); err == nil {
+			totalBytes += st.Size()
+		}
+	}
 	// Progress aggregator
-	agg := &progressAgg{total: totalBytes, start: time.Now()}
+	agg := &progressAgg{total: totalBytes, files: len(pairs) + len(links), start: time.Now()}
 	// UI / ticker setup
 	stopCh := make(chan struct{})
 	interactive := !noProgress && isTTY()
@@ -602,81 +1608,430 @@ func copyAll(ctx context.Context, pairs [][2]string, manifestPath string, worker
 			}
 		}()
 	} else {
-		// Non-interactive: print total line each second
-		go func() {
-			ticker := time.NewTicker(1 * time.Second)
-			defer ticker.Stop()
-			for {
+		go printTotalLines(agg, &mu, stopCh)
+	}
+	mf, err := os.OpenFile(manifestPath, os.O_CREATE|os.O_WRONLY|os.O_APPEND, 0o644)
+	if err != nil {
+		// Log error but continue - manifest is optional
+		fmt.Fprintf(os.Stderr, "warning: failed to open manifest file: %v\n", err)
+		return stats
+	}
+	mw := bufio.NewWriter(mf)
+	lastFlush := time.Now()
+	// writeManifest is called with mu held
+	writeManifest := func(rec ManifestRec) {
+		if report != nil {
+			// write errors are sticky and reported by the caller after Flush
+			_ = report.Write([]string{rec.Src, rec.Dst, strconv.FormatInt(rec.Size, 10), rec.Status, rec.Message, strconv.Itoa(rec.Code)})
+		}
+		b, err := json.Marshal(rec)
+		if err != nil {
+			// Log JSON marshaling error but continue
+			fmt.Fprintf(os.Stderr, "warning: failed to marshal manifest record: %v\n", err)
+			return
+		}
+		if _, err := mw.Write(b); err != nil {
+			fmt.Fprintf(os.Stderr, "warning: failed to write manifest: %v\n", err)
+			return
+		}
+		if err := mw.WriteByte('\n'); err != nil {
+			fmt.Fprintf(os.Stderr, "warning: failed to write manifest newline: %v\n", err)
+			return
+		}
+		// Persist progress now and then so a crash or power loss during a long
+		// run still leaves a record of the files that completed
+		if time.Since(lastFlush) >= manifestFlushInterval {
+			lastFlush = time.Now()
+			if err := mw.Flush(); err == nil {
+				_ = mf.Sync()
+			}
+		}
+	}
+	// notAttempted records a file left alone, or cut short by the stop, after an
+	// interrupt or --fail-fast stop.
+	// It is not an error; a later --resume run picks it up.
+	notAttempted := func(src, dst string) {
+		agg.FileDone()
+		st, _ := os.Stat(src)
+		agg.Pass(safeSize(st))
+		mu.Lock()
+		stats.notAttempted++
+		rec := ManifestRec{Src: src, Dst: dst, Size: safeSize(st), MTime: safeMTime(st), Priority: 0, Status: "cancelled", Message: "not attempted", Ts: float64(time.Now().UnixNano()) / 1e9}
+		writeManifest(rec)
+		mu.Unlock()
+	}
+	record := func(src, dst, status, msg string, code int) {
+		if status == "error" && failFast && ctx.Err() != nil {
+			// cut short by the --fail-fast stop, not failed in its own right
+			notAttempted(src, dst)
+			return
+		}
+		agg.FileDone()
+		st, _ := os.Stat(src)
+		mu.Lock()
+		switch status {
+		case "linked":
+			stats.copied++
+			stats.linkedBytes += safeSize(st)
+		case "copied":
+			stats.copied++
+		case "skipped":
+			stats.skipped++
+			stats.skipReasons[msg]++
+			agg.Pass(safeSize(st))
+		case "error":
+			stats.errors++
+			agg.Pass(safeSize(st))
+			stats.failures = append(stats.failures, copyFailure{path: src, message: msg})
+			// Report failures as they happen, not only in the manifest and final summary
+			line := fmt.Sprintf("Error: %s: %s", src, msg)
+			if logsCh != nil {
 				select {
-				case <-stopCh:
-					return
-				case <-ticker.C:
-					done := agg.Done()
-					elapsed := time.Since(agg.start).Seconds()
-					speed := float64(0)
-					if elapsed > 0 {
-						speed = float64(done) / elapsed
+				case logsCh <- line:
+				default:
+				}
+			} else {
+				fmt.Fprintln(os.Stderr, line)
+			}
+		}
+		rec := ManifestRec{Src: src, Dst: dst, Size: safeSize(st), MTime: safeMTime(st), Priority: 0, Status: status, Message: msg, Code: code, Ts: float64(time.Now().UnixNano()) / 1e9}
+		writeManifest(rec)
+		mu.Unlock()
+		if status == "error" && failFast {
+			stop()
+		}
+	}
+	worker := func() {
+		defer wg.Done()
+		for p := range jobs {
+			src, dst := p[0], p[1]
+			if ctx.Err() != nil {
+				// drain the queue without starting new copies
+				notAttempted(src, dst)
+				continue
+			}
+			status, msg, code := copyOneWithProgress(ctx, src, dst, agg, &mu, logsCh, interactive)
+			record(src, dst, status, msg, code)
+		}
+	}
+	for i := 0; i < workers; i++ {
+		wg.Add(1)
+		go worker()
+	}
+	fed := 0
+	for _, p := range pairs {
+		if failFast && ctx.Err() != nil {
+			break
+		}
+		jobs <- p
+		fed++
+	}
+	close(jobs)
+	wg.Wait()
+	for _, p := range pairs[fed:] {
+		notAttempted(p[0], p[1])
+	}
+	// Links need their targets in place, so they run after all copies
+	for _, l := range links {
+		if ctx.Err() != nil {
+			notAttempted(l.src, l.dst)
+			continue
+		}
+		status, msg, code := linkOrCopy(ctx, l, agg, &mu, logsCh, interactive)
+		record(l.src, l.dst, status, msg, code)
+	}
+	close(stopCh)
+	if err := mw.Flush(); err != nil {
+		fmt.Fprintf(os.Stderr, "warning: failed to flush manifest: %v\n", err)
+	}
+	if err := mf.Close(); err != nil {
+		fmt.Fprintf(os.Stderr, "warning: failed to close manifest file: %v\n", err)
+	}
+	stats.bytes = agg.Done()
+	return stats
+}
+
+// restoreDirTimes gives each [src, dst] directory pair, and their parents below
+// destDir, the modification time of the source directory. It runs after all files
+// are copied, deepest directories first, so creating children cannot bump a parent again.
+func restoreDirTimes(pairs [][2]string, destDir string) {
+	dirs := map[string]string{} // dst dir -> src dir
+	for _, p := range pairs {
+		src, dst := p[0], p[1]
+		// dst mirrors src below destDir, so walk both up in lockstep
+		for dst != destDir && prefixOf(dst, destDir) {
+			if _, seen := dirs[dst]; seen {
+				break
+			}
+			dirs[dst] = src
+			src, dst = filepath.Dir(src), filepath.Dir(dst)
+		}
+	}
+	order := make([]string, 0, len(dirs))
+	for d := range dirs {
+		order = append(order, d)
+	}
+	// a child path is always longer than its parent
+	sort.Slice(order, func(i, j int) bool { return len(order[i]) > len(order[j]) })
+	for _, dst := range order {
+		st, err := os.Stat(dirs[dst])
+		if err != nil || !st.IsDir() {
+			continue
+		}
+		_ = os.Chtimes(dst, time.Now(), st.ModTime())
+	}
+}
+
+// sameContent reports whether two files hold identical bytes. Any read error
+// counts as a difference so the file gets copied again.
+func sameContent(a, b string) bool {
+	fa, err := os.Open(a)
+	if err != nil {
+		return false
+	}
+	defer fa.Close()
+	fb, err := os.Open(b)
+	if err != nil {
+		return false
+	}
+	defer fb.Close()
+	bufA := make([]byte, 1<<20)
+	bufB := make([]byte, 1<<20)
+	for {
+		na, ea := io.ReadFull(fa, bufA)
+		nb, eb := io.ReadFull(fb, bufB)
+		if na != nb || !bytes.Equal(bufA[:na], bufB[:nb]) {
+			return false
+		}
+		if ea != nil || eb != nil {
+			// both files must have ended at the same point
+			return (ea == io.EOF || ea == io.ErrUnexpectedEOF) && (eb == io.EOF || eb == io.ErrUnexpectedEOF)
+		}
+	}
+}
+
+// verifyStats is the result of --verify-only. Files that were never backed up
+// (not on the destination) are counted but are not mismatches.
+type verifyStats struct {
+	checked    int
+	ok         int // content compared and identical
+	missing    int
+	unchecked  int // left when the run was interrupted
+	sizeOnly   int // matched by size; content not read (outside --verify-min-size/--verify-max-size)
+	mismatches []copyFailure
+}
+
+// existingResult is what filterExisting leaves to do.
+type existingResult struct {
+	toCopy    [][2]string
+	skipped   int       // already on the destination with the same size (and content)
+	timeFixes []timeFix // with syncTimes: skipped files whose modification time differs
+}
+
+// timeFix is a destination file whose modification time should become mtime.
+type timeFix struct {
+	dst   string
+	mtime time.Time
+}
+
+// filterExisting drops plans whose destination already holds the file (same
+// size, and same bytes with --compare-content). Nothing is written here, so a
+// dry run can use it too; timestamp fixes are applied by applyTimeFixes.
+func filterExisting(plans [][2]string, keepBoth, syncTimes bool) existingResult {
+	res := existingResult{toCopy: make([][2]string, 0, len(plans))}
+	planned := make(map[string]struct{}, len(plans))
+	for _, p := range plans {
+		planned[p[1]] = struct{}{}
+	}
+next:
+	for _, p := range plans {
+		src, dst := p[0], p[1]
+		if _, err := os.Stat(dst); err == nil {
+			kept := []string{dst}
+			if keepBoth {
+				// an earlier --keep-both run may already have copied src next to dst;
+				// variants that are themselves planned destinations belong to other files
+				for _, v := range existingVariants(dst) {
+					if _, ok := planned[v]; !ok {
+						kept = append(kept, v)
 					}
-					remaining := agg.total - done
-					eta := "--:--:--"
-					if speed > 1 {
-						eta = formatETA(float64(remaining) / speed)
+				}
+			}
+			for _, k := range kept {
+				if sst, st, ok := sameAsSource(src, k); ok {
+					res.skipped++
+					// FAT stores times in 2s steps, so smaller differences are not drift
+					if syncTimes && st.ModTime().Sub(sst.ModTime()).Abs() >= 2*time.Second {
+						res.timeFixes = append(res.timeFixes, timeFix{dst: k, mtime: sst.ModTime()})
 					}
-					mu.Lock()
-					fmt.Printf("[TOTAL] %s / %s (%.1f%%) | %s/s | ETA %s\n", humanSize(done), humanSize(agg.total), percent(done, agg.total), humanSize(int64(speed)), eta)
-					mu.Unlock()
+					continue next
 				}
 			}
-		}()
+			if keepBoth {
+				// Leave the existing entry alone and copy next to it
+				dst = availableName(dst, planned)
+				planned[dst] = struct{}{}
+				p = [2]string{src, dst}
+			}
+		}
+		res.toCopy = append(res.toCopy, p)
 	}
-	mf, err := os.OpenFile(manifestPath, os.O_CREATE|os.O_WRONLY|os.O_APPEND, 0o644)
-	if err != nil {
-		// Log error but continue - manifest is optional
-		fmt.Fprintf(os.Stderr, "warning: failed to open manifest file: %v\n", err)
-		return copied, errorsN
+	return res
+}
+
+// sameAsSource reports whether dst is a regular file with the size of src (and
+// its bytes, with --compare-content), returning both file infos.
+func sameAsSource(src, dst string) (srcSt, dstSt os.FileInfo, ok bool) {
+	st, err := os.Stat(dst)
+	if err != nil || !st.Mode().IsRegular() {
+		return nil, nil, false
 	}
-	mw := bufio.NewWriter(mf)
-	writeManifest := func(rec ManifestRec) {
-		b, err := json.Marshal(rec)
-		if err != nil {
-			// Log JSON marshaling error but continue
-			fmt.Fprintf(os.Stderr, "warning: failed to marshal manifest record: %v\n", err)
-			return
+	sst, err := os.Stat(src)
+	if err != nil || sst.Size() != st.Size() {
+		return nil, nil, false
+	}
+	if compareContent && !sameContent(src, dst) {
+		return nil, nil, false
+	}
+	return sst, st, true
+}
+
+// applyTimeFixes sets the modification times (a metadata-only update, no data
+// is rewritten) and returns how many succeeded.
+func applyTimeFixes(fixes []timeFix) int {
+	n := 0
+	for _, f := range fixes {
+		if os.Chtimes(f.dst, time.Now(), f.mtime) == nil {
+			n++
 		}
-		if _, err := mw.Write(b); err != nil {
-			fmt.Fprintf(os.Stderr, "warning: failed to write manifest: %v\n", err)
-			return
+	}
+	return n
+}
+
+// limitRun returns how many leading plans fit in maxFiles files and maxBytes
+// bytes of source data (0 = no limit). Plans are in priority order, so it stops
+// at the first file that does not fit rather than skipping ahead to smaller ones.
+// The first file is always allowed, even when it alone exceeds maxBytes, so a
+// file larger than the limit cannot stall every later --resume run.
+func limitRun(plans [][2]string, maxFiles int, maxBytes int64) int {
+	var used int64
+	for i, p := range plans {
+		if maxFiles > 0 && i >= maxFiles {
+			return i
 		}
-		if err := mw.WriteByte('\n'); err != nil {
-			fmt.Fprintf(os.Stderr, "warning: failed to write manifest newline: %v\n", err)
-			return
+		if maxBytes > 0 {
+			if st, err := os.Stat(p[0]); err == nil {
+				used += st.Size()
+			}
+			if used > maxBytes && i > 0 {
+				return i
+			}
+		}
+	}
+	return len(plans)
+}
+
+// checkVerifyDest makes sure the backup folder to verify exists.
+func checkVerifyDest(dir string) error {
+	st, err := os.Stat(dir)
+	if err != nil {
+		return fmt.Errorf("--verify-only: no backup at %s: %w", dir, err)
+	}
+	if !st.IsDir() {
+		return fmt.Errorf("--verify-only: %s is not a folder", dir)
+	}
+	return nil
+}
+
+// verifyRange limits byte-by-byte comparison to files within [min, max]; others
+// are only checked by size. 0 means no limit.
+type verifyRange struct {
+	min, max int64
+}
+
+func (r verifyRange) contains(size int64) bool {
+	return (r.min <= 0 || size >= r.min) && (r.max <= 0 || size <= r.max)
+}
+
+// verifyPlans compares every planned destination that exists with its source,
+// spreading the files over workers (runtime.NumCPU() when <= 0).
+// Progress (bytes of destination files checked) is shown on the TUI bar.
+func verifyPlans(ctx context.Context, plans [][2]string, workers int, limits verifyRange, tui *TUI) verifyStats {
+	var vs verifyStats
+	existing := make([][2]string, 0, len(plans))
+	var total int64
+	for _, p := range plans {
+		st, err := os.Stat(p[1])
+		if err != nil {
+			vs.missing++
+			continue
 		}
+		existing = append(existing, p)
+		total += st.Size()
 	}
+	if workers <= 0 {
+		workers = runtime.NumCPU()
+	}
+	agg := &progressAgg{total: total, files: len(existing), start: time.Now()}
+	tui.DrawTop(agg)
+	jobs := make(chan [2]string, workers*2)
+	var wg sync.WaitGroup
+	var mu sync.Mutex
+	if noProgress || !isTTY() {
+		// no TUI to draw on: the same [TOTAL] lines as the copy phase
+		stopCh := make(chan struct{})
+		defer close(stopCh)
+		go printTotalLines(agg, &mu, stopCh)
+	}
+	lastReport := time.Now()
 	worker := func() {
 		defer wg.Done()
 		for p := range jobs {
 			src, dst := p[0], p[1]
-			select {
-			case <-ctx.Done():
-				// interrupted
+			if ctx.Err() != nil {
 				mu.Lock()
-				errorsN++
-				rec := ManifestRec{Src: src, Dst: dst, Size: 0, MTime: 0, Priority: 0, Status: "cancelled", Message: "interrupted", Ts: float64(time.Now().UnixNano()) / 1e9}
-				writeManifest(rec)
+				vs.unchecked++
+				mu.Unlock()
+				continue
+			}
+			dstSt, err := os.Stat(dst)
+			if err != nil {
+				agg.FileDone()
+				mu.Lock()
+				vs.missing++
 				mu.Unlock()
 				continue
-			default:
 			}
-			status, msg := copyOneWithProgress(ctx, src, dst, agg, &mu, logsCh, interactive)
-			st, _ := os.Stat(src)
+			msg := ""
+			sizeOnly := false
+			srcSt, err := os.Stat(src)
+			switch {
+			case err != nil:
+				msg = "source unreadable: " + err.Error()
+			case !dstSt.Mode().IsRegular() || dstSt.Size() != srcSt.Size():
+				msg = "size differs from source"
+			case !limits.contains(srcSt.Size()):
+				sizeOnly = true
+			case !sameContent(src, dst):
+				msg = "content differs from source"
+			}
+			agg.Add(dstSt.Size())
+			agg.FileDone()
 			mu.Lock()
-			if status == "copied" {
-				copied++
-			} else if status == "error" {
-				errorsN++
+			vs.checked++
+			switch {
+			case msg != "":
+				vs.mismatches = append(vs.mismatches, copyFailure{path: dst, message: msg})
+				tui.AppendLog(fmt.Sprintf("Mismatch: %s: %s", dst, msg))
+			case sizeOnly:
+				vs.sizeOnly++
+			default:
+				vs.ok++
+			}
+			if tui != nil && time.Since(lastReport) > 500*time.Millisecond {
+				tui.AppendLog(fmt.Sprintf("Verifying %d/%d files...", vs.checked, len(existing)))
+				lastReport = time.Now()
 			}
-			rec := ManifestRec{Src: src, Dst: dst, Size: safeSize(st), MTime: safeMTime(st), Priority: 0, Status: status, Message: msg, Ts: float64(time.Now().UnixNano()) / 1e9}
-			writeManifest(rec)
 			mu.Unlock()
 		}
 	}
@@ -684,19 +2039,49 @@ func copyAll(ctx context.Context, pairs [][2]string, manifestPath string, worker
 		wg.Add(1)
 		go worker()
 	}
-	for _, p := range pairs {
+	fed := 0
+	for _, p := range existing {
+		if ctx.Err() != nil {
+			break
+		}
 		jobs <- p
+		fed++
 	}
 	close(jobs)
 	wg.Wait()
-	close(stopCh)
-	if err := mw.Flush(); err != nil {
-		fmt.Fprintf(os.Stderr, "warning: failed to flush manifest: %v\n", err)
+	vs.unchecked += len(existing) - fed
+	// workers finish out of order; keep the report stable
+	sort.Slice(vs.mismatches, func(i, j int) bool { return vs.mismatches[i].path < vs.mismatches[j].path })
+	return vs
+}
+
+// exitCode is the process exit code for a --verify-only run: differences
+// outrank an interrupted check, which still fails the run.
+func (vs verifyStats) exitCode() int {
+	switch {
+	case len(vs.mismatches) > 0 || vs.missing > 0:
+		return exitVerifyMismatch
+	case vs.unchecked > 0:
+		return exitCopyErrors
 	}
-	if err := mf.Close(); err != nil {
-		fmt.Fprintf(os.Stderr, "warning: failed to close manifest file: %v\n", err)
+	return 0
+}
+
+func printVerifyStats(vs verifyStats) {
+	fmt.Fprintf(stdout, "Verify complete: checked=%d, ok=%d, mismatched=%d, not on destination=%d\n", vs.checked, vs.ok, len(vs.mismatches), vs.missing)
+	if vs.sizeOnly > 0 {
+		fmt.Fprintf(stdout, "  size only (outside the verify size range, content not read): %d\n", vs.sizeOnly)
+	}
+	if vs.unchecked > 0 {
+		fmt.Fprintf(stdout, "Verify interrupted: %d files not checked\n", vs.unchecked)
+	}
+	for i, f := range vs.mismatches {
+		if i == maxListedFailures {
+			fmt.Fprintf(stdout, "  ... and %d more\n", len(vs.mismatches)-i)
+			break
+		}
+		fmt.Fprintf(stdout, "  mismatch: %s: %s\n", f.path, f.message)
 	}
-	return copied, errorsN
 }
 
 func safeSize(fi os.FileInfo) int64 {
@@ -712,14 +2097,16 @@ func safeMTime(fi os.FileInfo) int64 {
 	return fi.ModTime().Unix()
 }
 
-func copyOneWithProgress(ctx context.Context, src, dst string, agg *progressAgg, mu *sync.Mutex, logsCh chan string, interactive bool) (string, string) {
+// copyOneWithProgress returns the manifest status and message for src, and the
+// OS error code when it failed.
+func copyOneWithProgress(ctx context.Context, src, dst string, agg *progressAgg, mu *sync.Mutex, logsCh chan string, interactive bool) (string, string, int) {
 	if err := os.MkdirAll(filepath.Dir(dst), 0o755); err != nil {
-		return "error", err.Error()
+		return "error", mkdirErrMessage(filepath.Dir(dst), err), errCode(err)
 	}
 	if dstSt, err := os.Stat(dst); err == nil {
 		if srcSt, err2 := os.Stat(src); err2 == nil {
-			if dstSt.Size() == srcSt.Size() {
-				return "skipped", "exists-same-size"
+			if dstSt.Size() == srcSt.Size() && (!compareContent || sameContent(src, dst)) {
+				return "skipped", skipExists, 0
 			}
 		}
 	}
@@ -740,15 +2127,31 @@ func copyOneWithProgress(ctx context.Context, src, dst string, agg *progressAgg,
 			}
 		}
 	} else if !interactive {
-		fmt.Printf("Start: %s\n", filepath.Base(src))
+		fmt.Fprintf(stdout, "Start: %s\n", filepath.Base(src))
 	}
-	if err := copyFileWithProgress(ctx, src, tmp, agg, mu, logsCh, interactive); err != nil {
-		_ = os.Remove(tmp)
-		return "error", err.Error()
+	if err := copyWithTimeout(ctx, src, tmp, agg, mu, logsCh, interactive); err != nil {
+		var abandoned *abandonedError
+		if !errors.As(err, &abandoned) {
+			// an abandoned copy may still hold tmp open; copyWithTimeout removes it later
+			_ = os.Remove(tmp)
+		}
+		if skipLocked && isFileInUse(err) {
+			return "skipped", skipInUse, 0
+		}
+		if _, serr := os.Lstat(src); os.IsNotExist(serr) {
+			// deleted since the scan, common when backing up folders in use
+			return "skipped", skipRemoved, 0
+		}
+		return "error", copyErrMessage(err), errCode(err)
 	}
 	if err := os.Rename(tmp, dst); err != nil {
 		_ = os.Remove(tmp)
-		return "error", err.Error()
+		return "error", copyErrMessage(err), errCode(err)
+	}
+	if durable {
+		// persist the rename; directories cannot be synced on Windows, where
+		// NTFS journals it anyway
+		syncDir(filepath.Dir(dst))
 	}
 	if logsCh != nil {
 		select {
@@ -756,24 +2159,206 @@ func copyOneWithProgress(ctx context.Context, src, dst string, agg *progressAgg,
 		default:
 		}
 	} else if !interactive {
-		fmt.Printf("Done: %s\n", filepath.Base(src))
+		fmt.Fprintf(stdout, "Done: %s\n", filepath.Base(src))
+	}
+	return "copied", "ok", 0
+}
+
+// syncDir flushes a directory's entries (best effort).
+func syncDir(dir string) {
+	if d, err := os.Open(dir); err == nil {
+		_ = d.Sync()
+		d.Close()
 	}
-	return "copied", "ok"
+}
+
+// errFileTimedOut marks a copy abandoned after --file-timeout.
+var errFileTimedOut = errors.New("timed out")
+
+// abandonedError is returned for a copy that copyWithTimeout stopped waiting
+// for; the copy may still hold its .part file, which is removed once it returns.
+type abandonedError struct{ err error }
+
+func (e *abandonedError) Error() string { return e.err.Error() }
+func (e *abandonedError) Unwrap() error { return e.err }
+
+// copyFileFunc is the copy copyWithTimeout runs; tests substitute a slow one.
+var copyFileFunc = copyFileWithProgress
+
+// copyWithTimeout runs copyFileWithProgress but gives up after fileTimeout. A
+// copy stuck in a blocking read (e.g. a dead network share) cannot be interrupted,
+// so it is abandoned, on timeout as on cancel: its bytes are taken back out of
+// agg and its .part file (tmp) is removed once it finally returns. Until then it
+// keeps its handle and copy buffer.
+func copyWithTimeout(ctx context.Context, src, tmp string, agg *progressAgg, mu *sync.Mutex, logsCh chan string, interactive bool) error {
+	if fileTimeout <= 0 {
+		return copyFileFunc(ctx, src, tmp, agg, mu, logsCh, interactive)
+	}
+	fctx, cancel := context.WithTimeout(ctx, fileTimeout)
+	defer cancel()
+	var child *progressAgg
+	if agg != nil {
+		child = &progressAgg{parent: agg}
+	}
+	done := make(chan error, 1)
+	go func() { done <- copyFileFunc(fctx, src, tmp, child, mu, logsCh, interactive) }()
+	abandon := func(reason error) error {
+		if child != nil {
+			child.detach()
+		}
+		go func() {
+			<-done
+			_ = os.Remove(tmp)
+		}()
+		return &abandonedError{err: reason}
+	}
+	select {
+	case err := <-done:
+		if err != nil && ctx.Err() == nil && fctx.Err() != nil {
+			// the copy noticed the deadline itself; done is drained, so re-feed it
+			done <- err
+			return abandon(fmt.Errorf("%w after %s", errFileTimedOut, fileTimeout))
+		}
+		return err
+	case <-fctx.Done():
+		if ctx.Err() != nil {
+			return abandon(fmt.Errorf("cancelled"))
+		}
+		return abandon(fmt.Errorf("%w after %s", errFileTimedOut, fileTimeout))
+	}
+}
+
+// copyErrMessage turns a copy error into the manifest/log message, naming the
+// causes a user can act on instead of just echoing the raw OS error.
+func copyErrMessage(err error) string {
+	if isDiskFull(err) {
+		return "destination disk is full: " + err.Error()
+	}
+	if isFileInUse(err) {
+		return "file is in use by another process: " + err.Error()
+	}
+	return err.Error()
+}
+
+// mkdirErrMessage explains a failure to create the destination folder dir,
+// naming the file when an existing file sits where a folder is needed.
+func mkdirErrMessage(dir string, err error) string {
+	for d := dir; ; d = filepath.Dir(d) {
+		if st, serr := os.Stat(d); serr == nil {
+			if !st.IsDir() {
+				return "destination folder cannot be created, a file is in the way: " + d
+			}
+			break
+		}
+		if filepath.Dir(d) == d {
+			break
+		}
+	}
+	return copyErrMessage(err)
+}
+
+// errCode extracts the OS error number from err, 0 when there is none (e.g. a
+// timeout or a short read).
+func errCode(err error) int {
+	var errno syscall.Errno
+	if errors.As(err, &errno) {
+		return int(errno)
+	}
+	return 0
 }
 
 // copyFileWithProgress used instead of legacy copyFile
 
 type progressAgg struct {
-	total int64
-	done  int64 // atomic
-	start time.Time
+	total     int64
+	done      int64 // atomic, bytes actually transferred
+	passed    int64 // atomic, bytes of skipped and failed files, counted towards percent/ETA only
+	files     int   // files in this run
+	filesDone int64 // atomic
+	start     time.Time
+
+	// recent (time, done) samples for the displayed speed and ETA
+	sampleMu sync.Mutex
+	samples  [speedSamples]speedSample
+	next     int // slot for the next sample
+	nSamples int
+
+	// set on the per-file child that copyWithTimeout hands to a copy it may abandon
+	parent   *progressAgg
+	detachMu sync.Mutex
+	detached bool
+}
+
+// The displayed speed covers about the last speedWindow rather than the whole
+// run, so the ETA adapts when a phase of small files gives way to large ones and
+// vice versa. Snapshots are sampled at most every speedSampleGap; the ring holds
+// enough of them to always reach back a full window.
+const (
+	speedWindow    = 10 * time.Second
+	speedSampleGap = 500 * time.Millisecond
+	speedSamples   = int(speedWindow/speedSampleGap) + 2
+)
+
+type speedSample struct {
+	at   time.Time
+	done int64
+}
+
+// recentSpeed records done at now (at most once per speedSampleGap) and returns
+// the throughput since the newest sample at least speedWindow old, or since the
+// oldest one early in the run. When snapshots come less often than speedWindow
+// (a long --progress-interval) that is simply the previous one. It returns false
+// until the window spans a second.
+func (p *progressAgg) recentSpeed(now time.Time, done int64) (float64, bool) {
+	p.sampleMu.Lock()
+	defer p.sampleMu.Unlock()
+	last := p.samples[(p.next+speedSamples-1)%speedSamples]
+	if p.nSamples == 0 || now.Sub(last.at) >= speedSampleGap {
+		p.samples[p.next] = speedSample{at: now, done: done}
+		p.next = (p.next + 1) % speedSamples
+		if p.nSamples < speedSamples {
+			p.nSamples++
+		}
+	}
+	ref := p.samples[(p.next+speedSamples-p.nSamples)%speedSamples]
+	for k := 0; k < p.nSamples; k++ {
+		// newest first
+		if s := p.samples[(p.next+speedSamples-1-k)%speedSamples]; now.Sub(s.at) >= speedWindow {
+			ref = s
+			break
+		}
+	}
+	dt := now.Sub(ref.at).Seconds()
+	if dt < 1 {
+		return 0, false
+	}
+	return float64(done-ref.done) / dt, true
+}
+
+// A premature EOF (fewer bytes than the file size) is retried this many times
+// before the copy fails.
+const (
+	maxEOFRetries = 3
+	eofRetryDelay = 500 * time.Millisecond
+)
+
+func shortReadError(got, want int64) error {
+	return fmt.Errorf("source ended early: read %s of %s (file shrank or network read failed)", humanSize(got), humanSize(want))
 }
 
 // --- Copy performance helpers ---
 // Large reusable buffers significantly reduce syscalls and improve throughput on HDD/USB.
+// 8 MiB buffer strikes a good balance for spinning disks and USB drives; network
+// shares or very fast media may prefer another size (--buffer-size).
+var copyBufSize = 8 << 20 // 8 MiB (runtime adjustable)
+
+const (
+	minCopyBufSize = 64 << 10 // 64 KiB
+	maxCopyBufSize = 64 << 20 // 64 MiB
+)
+
 var copyBufPool = sync.Pool{New: func() any {
-	// 8 MiB buffer strikes a good balance for spinning disks and USB drives
-	b := make([]byte, 8<<20)
+	b := make([]byte, copyBufSize)
 	return &b
 }}
 
@@ -809,10 +2394,76 @@ func smallBufPoolPut(b *[]byte) {
 // Platform-specific openFileSequentialRead/openFileSequentialWrite are implemented
 // in open_unix.go and open_windows.go.
 
-func (p *progressAgg) Add(n int64) { atomic.AddInt64(&p.done, n) }
+func (p *progressAgg) Add(n int64) {
+	if p.parent == nil {
+		atomic.AddInt64(&p.done, n)
+		return
+	}
+	p.detachMu.Lock()
+	defer p.detachMu.Unlock()
+	if !p.detached {
+		atomic.AddInt64(&p.done, n)
+		p.parent.Add(n)
+	}
+}
+
+// detach stops a child from forwarding to its parent and takes back the bytes it
+// already forwarded, so an abandoned copy leaves no trace in the totals.
+func (p *progressAgg) detach() {
+	p.detachMu.Lock()
+	defer p.detachMu.Unlock()
+	if !p.detached {
+		p.detached = true
+		p.parent.Add(-atomic.LoadInt64(&p.done))
+	}
+}
+
 func (p *progressAgg) Done() int64 { return atomic.LoadInt64(&p.done) }
 
-func copyFileWithProgress(ctx context.Context, src, dst string, agg *progressAgg, mu *sync.Mutex, logsCh chan string, interactive bool) error {
+// FileDone counts one more file as processed, whatever its outcome.
+func (p *progressAgg) FileDone() { atomic.AddInt64(&p.filesDone, 1) }
+
+// Pass accounts for a file that ended without being copied, so the bar does not
+// sit at 0% through a run that skips most files.
+func (p *progressAgg) Pass(n int64) { atomic.AddInt64(&p.passed, n) }
+
+// progressSnapshot is a point-in-time view of the overall copy progress.
+// The TUI and the console [TOTAL] line both render from it so the rate math lives here only.
+type progressSnapshot struct {
+	done      int64
+	total     int64
+	filesDone int
+	files     int
+	elapsed   float64 // seconds since the copy started
+	speed     float64 // bytes per second over the last few seconds (whole run at first)
+	percent   float64
+	eta       string
+}
+
+func (p *progressAgg) Snapshot(now time.Time) progressSnapshot {
+	s := progressSnapshot{done: p.Done(), total: p.total, eta: "--:--:--"}
+	s.filesDone, s.files = int(atomic.LoadInt64(&p.filesDone)), p.files
+	s.elapsed = now.Sub(p.start).Seconds()
+	if s.elapsed > 0 {
+		s.speed = float64(s.done) / s.elapsed
+	}
+	if v, ok := p.recentSpeed(now, s.done); ok {
+		s.speed = v
+	}
+	// a failed file may have transferred part of its bytes before being passed
+	accounted := min(s.done+atomic.LoadInt64(&p.passed), s.total)
+	s.percent = percent(accounted, s.total)
+	if s.total <= 0 {
+		// nothing left to transfer: report done rather than stuck at 0%
+		s.percent = 100
+	}
+	if s.speed > 1 {
+		s.eta = formatETA(float64(s.total-accounted) / s.speed)
+	}
+	return s
+}
+
+func copyFileWithProgress(ctx context.Context, src, dst string, agg *progressAgg, mu *sync.Mutex, logsCh chan string, interactive bool) (err error) {
 	// Use OS-optimized open for better throughput
 	in, err := openFileSequentialRead(src)
 	if err != nil {
@@ -828,8 +2479,18 @@ func copyFileWithProgress(ctx context.Context, src, dst string, agg *progressAgg
 		return err
 	}
 	defer out.Close()
+	if durable {
+		// runs before the Close above: a failed flush fails the copy
+		defer func() {
+			if err == nil {
+				err = out.Sync()
+			}
+		}()
+	}
 	// Preallocate destination size when possible to reduce fragmentation.
 	_ = out.Truncate(st.Size())
+	// Creation time survives the writes below and the rename of the .part file
+	setCreationTime(out, st)
 
 	// Fast path for small files: single read + single write.
 	if st.Size() <= int64(smallFileThreshold) {
@@ -851,7 +2512,7 @@ func copyFileWithProgress(ctx context.Context, src, dst string, agg *progressAgg
 				}
 			} else if !interactive {
 				mu.Lock()
-				fmt.Printf("[FILE] %s\n", final)
+				fmt.Fprintf(stdout, "[FILE] %s\n", final)
 				mu.Unlock()
 			}
 			return nil
@@ -893,7 +2554,7 @@ func copyFileWithProgress(ctx context.Context, src, dst string, agg *progressAgg
 				}
 			} else if !interactive {
 				mu.Lock()
-				fmt.Printf("[FILE] %s\n", final)
+				fmt.Fprintf(stdout, "[FILE] %s\n", final)
 				mu.Unlock()
 			}
 		}
@@ -909,6 +2570,9 @@ func copyFileWithProgress(ctx context.Context, src, dst string, agg *progressAgg
 		if err != nil {
 			return err
 		}
+		if n < st.Size() {
+			return shortReadError(n, st.Size())
+		}
 		select {
 		case <-ctx.Done():
 			return fmt.Errorf("cancelled")
@@ -932,7 +2596,7 @@ func copyFileWithProgress(ctx context.Context, src, dst string, agg *progressAgg
 				}
 			} else if !interactive {
 				mu.Lock()
-				fmt.Printf("[FILE] %s\n", final)
+				fmt.Fprintf(stdout, "[FILE] %s\n", final)
 				mu.Unlock()
 			}
 		}
@@ -942,62 +2606,47 @@ func copyFileWithProgress(ctx context.Context, src, dst string, agg *progressAgg
 	bufPtr := bufPoolGet()
 	defer bufPoolPut(bufPtr)
 	buf := *bufPtr
-	var done int64
 	started := time.Now()
 	lastPrint := time.Time{}
 	name := filepath.Base(src)
-	for {
-		nr, er := in.Read(buf)
-		if nr > 0 {
-			nw, ew := out.Write(buf[:nr])
-			if ew != nil {
-				return ew
-			}
-			if nw < nr {
-				return io.ErrShortWrite
-			}
-			done += int64(nw)
-			if agg != nil {
-				agg.Add(int64(nw))
-			}
+	done, err := copyStream(ctx, in, out, buf, st.Size(), func(n, copied int64) {
+		if agg != nil {
+			agg.Add(n)
+		}
+		// Throttled per-file progress (progressInterval)
+		now := time.Now()
+		if noProgress || now.Sub(lastPrint) < progressInterval {
+			return
+		}
+		elapsed := now.Sub(started).Seconds()
+		speed := float64(0)
+		if elapsed > 0 {
+			speed = float64(copied) / elapsed
+		}
+		remaining := st.Size() - copied
+		eta := "--:--:--"
+		if speed > 1 {
+			eta = formatETA(float64(remaining) / speed)
+		}
+		line := fmt.Sprintf("%s %5.1f%% | %s/s | ETA %s", name, percent(copied, st.Size()), humanSize(int64(speed)), eta)
+		if logsCh != nil {
 			select {
-			case <-ctx.Done():
-				return fmt.Errorf("cancelled")
+			case logsCh <- line:
 			default:
 			}
-			// Throttled per-file progress (1s)
-			now := time.Now()
-			if !noProgress && now.Sub(lastPrint) >= time.Second {
-				elapsed := now.Sub(started).Seconds()
-				speed := float64(0)
-				if elapsed > 0 {
-					speed = float64(done) / elapsed
-				}
-				remaining := st.Size() - done
-				eta := "--:--:--"
-				if speed > 1 {
-					eta = formatETA(float64(remaining) / speed)
-				}
-				line := fmt.Sprintf("%s %5.1f%% | %s/s | ETA %s", name, percent(done, st.Size()), humanSize(int64(speed)), eta)
-				if logsCh != nil {
-					select {
-					case logsCh <- line:
-					default:
-					}
-				} else if !interactive {
-					mu.Lock()
-					fmt.Printf("[FILE] %s\n", line)
-					mu.Unlock()
-				}
-				lastPrint = now
-			}
-		}
-		if er != nil {
-			if er == io.EOF {
-				break
-			}
-			return er
+		} else if !interactive {
+			mu.Lock()
+			fmt.Fprintf(stdout, "[FILE] %s\n", line)
+			mu.Unlock()
 		}
+		lastPrint = now
+	})
+	if err != nil {
+		return err
+	}
+	if done < st.Size() {
+		// the preallocated .part would otherwise be padded with zeros
+		return shortReadError(done, st.Size())
 	}
 	// Finalize times
 	_ = os.Chtimes(dst, time.Now(), st.ModTime())
@@ -1015,13 +2664,59 @@ func copyFileWithProgress(ctx context.Context, src, dst string, agg *progressAgg
 			}
 		} else if !interactive {
 			mu.Lock()
-			fmt.Printf("[FILE] %s\n", final)
+			fmt.Fprintf(stdout, "[FILE] %s\n", final)
 			mu.Unlock()
 		}
 	}
 	return nil
 }
 
+// copyStream copies in to out through buf until EOF and returns the bytes
+// written, calling progress with each write's size and the running total. Some
+// SMB shares report EOF for a transient empty read, so an EOF before size bytes
+// is retried up to maxEOFRetries times, eofRetryDelay apart, unless ctx ends.
+func copyStream(ctx context.Context, in io.Reader, out io.Writer, buf []byte, size int64, progress func(n, done int64)) (int64, error) {
+	var done int64
+	eofRetries := 0
+	for {
+		nr, er := in.Read(buf)
+		if nr > 0 {
+			eofRetries = 0
+			nw, ew := out.Write(buf[:nr])
+			if ew != nil {
+				return done, ew
+			}
+			if nw < nr {
+				return done, io.ErrShortWrite
+			}
+			done += int64(nw)
+			progress(int64(nw), done)
+			select {
+			case <-ctx.Done():
+				return done, fmt.Errorf("cancelled")
+			default:
+			}
+		}
+		if er == nil {
+			continue
+		}
+		if er != io.EOF {
+			return done, er
+		}
+		if done >= size || eofRetries >= maxEOFRetries {
+			return done, nil
+		}
+		eofRetries++
+		t := time.NewTimer(eofRetryDelay)
+		select {
+		case <-ctx.Done():
+			t.Stop()
+			return done, fmt.Errorf("cancelled")
+		case <-t.C:
+		}
+	}
+}
+
 func percent(done, total int64) float64 {
 	if total <= 0 {
 		return 0
@@ -1045,7 +2740,7 @@ func formatETA(sec float64) string {
 
 // --- Console helpers for a static TOTAL line ---
 func isTTY() bool {
-	fi, err := os.Stdout.Stat()
+	fi, err := stdout.Stat()
 	if err != nil {
 		return false
 	}
@@ -1055,27 +2750,34 @@ func isTTY() bool {
 func printTotalLine(line string) {
 	if isTTY() {
 		// Carriage return + clear line + print without newline
-		fmt.Printf("\r\x1b[2K%s", line)
+		fmt.Fprintf(stdout, "\r\x1b[2K%s", line)
 	} else {
 		// Non-interactive: just print lines normally
-		fmt.Println(line)
+		fmt.Fprintln(stdout, line)
 	}
 }
 
-func formatTotalLine(agg *progressAgg) string {
-	done := agg.Done()
-	elapsed := time.Since(agg.start).Seconds()
-	speed := float64(0)
-	if elapsed > 0 {
-		speed = float64(done) / elapsed
-	}
-	remaining := agg.total - done
-	eta := "--:--:--"
-	if speed > 1 {
-		eta = formatETA(float64(remaining) / speed)
+// printTotalLines is the non-interactive progress display: it prints a
+// [TOTAL] line every progressInterval, with mu held, until stop is closed.
+func printTotalLines(agg *progressAgg, mu *sync.Mutex, stop <-chan struct{}) {
+	ticker := time.NewTicker(progressInterval)
+	defer ticker.Stop()
+	for {
+		select {
+		case <-stop:
+			return
+		case <-ticker.C:
+			mu.Lock()
+			fmt.Fprintln(stdout, formatTotalLine(agg))
+			mu.Unlock()
+		}
 	}
-	return fmt.Sprintf("[TOTAL] %s / %s (%.1f%%) | %s/s | ETA %s",
-		humanSize(done), humanSize(agg.total), percent(done, agg.total), humanSize(int64(speed)), eta)
+}
+
+func formatTotalLine(agg *progressAgg) string {
+	s := agg.Snapshot(time.Now())
+	return fmt.Sprintf("[TOTAL] %s / %s (%.1f%%) | %d/%d files | %s/s | ETA %s",
+		humanSize(s.done), humanSize(s.total), s.percent, s.filesDone, s.files, humanSize(int64(s.speed)), s.eta)
 }
 
 // ---------- Enhanced Cross-Platform TUI ----------
@@ -1095,9 +2797,7 @@ type teaProgram struct {
 	ready      bool
 	width      int
 	height     int
-	total      int64
-	done       int64
-	start      time.Time
+	agg        atomic.Pointer[progressAgg] // set once copying starts
 	logs       []string
 	styles     uiStyles
 	quitting   bool
@@ -1116,7 +2816,6 @@ type uiStyles struct {
 
 func NewTUI(cancelFunc context.CancelFunc) *TUI {
 	p := &teaProgram{
-		start:      time.Now(),
 		logs:       make([]string, 0),
 		cancelFunc: cancelFunc,
 	}
@@ -1152,6 +2851,7 @@ func NewTUI(cancelFunc context.CancelFunc) *TUI {
 
 	// forward logs into model
 	go func() {
+		var lastSend time.Time
 		for {
 			select {
 			case l := <-tui.logsCh:
@@ -1159,9 +2859,11 @@ func NewTUI(cancelFunc context.CancelFunc) *TUI {
 				if len(p.logs) > 1000 {
 					p.logs = p.logs[len(p.logs)-1000:]
 				}
-				// Send update to program
-				if tui.prog != nil {
+				// Send update to program, at most one per repaint interval: the
+				// 100ms tick repaints anyway and picks up the latest lines of a burst
+				if tui.prog != nil && time.Since(lastSend) >= 100*time.Millisecond {
 					tui.prog.Send(logUpdateMsg{})
+					lastSend = time.Now()
 				}
 			case <-tui.quitCh:
 				return
@@ -1234,24 +2936,11 @@ func (m *teaProgram) View() string {
 	header := m.styles.header.Render("🔄 USB Backuper - Intelligent Backup")
 
 	// Progress section
-	done := atomic.LoadInt64(&m.done)
-	total := m.total
-	percent := 0.0
-	if total > 0 {
-		percent = float64(done) * 100.0 / float64(total)
-	}
-
-	// Calculate speed
-	elapsed := time.Since(m.start).Seconds()
-	speed := float64(0)
-	if elapsed > 0.1 {
-		speed = float64(done) / elapsed
-	}
-	remaining := total - done
-	eta := "--:--:--"
-	if speed > 1 && remaining > 0 {
-		eta = formatETA(float64(remaining) / speed)
+	snap := progressSnapshot{eta: "--:--:--"}
+	if agg := m.agg.Load(); agg != nil {
+		snap = agg.Snapshot(time.Now())
 	}
+	percent := snap.percent
 
 	// Progress bar
 	barWidth := contentWidth - 12
@@ -1278,20 +2967,22 @@ func (m *teaProgram) View() string {
 	// Stats
 	stats := fmt.Sprintf(
 		"Transferred: %s / %s\n"+
+			"Files:       %d / %d\n"+
 			"Speed:       %s/s\n"+
 			"Elapsed:     %s\n"+
 			"ETA:         %s",
-		humanSize(done), humanSize(total),
-		humanSize(int64(speed)),
-		formatETA(elapsed),
-		eta,
+		humanSize(snap.done), humanSize(snap.total),
+		snap.filesDone, snap.files,
+		humanSize(int64(snap.speed)),
+		formatETA(snap.elapsed),
+		snap.eta,
 	)
 
 	progressContent := progressBar + "\n\n" + m.styles.info.Render(stats)
 	progressBox := m.styles.box.Width(contentWidth).Render(progressContent)
 
 	// Activity log section
-	logHeight := m.height - 18
+	logHeight := m.height - 19
 	if logHeight < 3 {
 		logHeight = 3
 	}
@@ -1340,12 +3031,11 @@ func (t *TUI) DrawStatic() {
 }
 
 func (t *TUI) DrawTop(agg *progressAgg) {
-	// Update model counters
+	// Point the model at the live counters; View takes its own snapshot
 	if t == nil || t.model == nil {
 		return
 	}
-	atomic.StoreInt64(&t.model.done, agg.Done())
-	t.model.total = agg.total
+	t.model.agg.Store(agg)
 	// Trigger re-render
 	if t.prog != nil {
 		t.prog.Send(progressUpdateMsg{})
@@ -1379,7 +3069,7 @@ func (t *TUI) Close() {
 			t.prog.Quit()
 		}
 		// leave alt screen
-		fmt.Print("\x1b[?25h\x1b[2J\x1b[H\x1b[?1049l")
+		fmt.Fprint(stdout, "\x1b[?25h\x1b[2J\x1b[H\x1b[?1049l")
 	})
 }
 
@@ -1492,7 +3182,11 @@ func getWindowsConsoleSize() (int, int) {
 	return 0, 0
 }
 
+// expandPath expands a leading ~ and environment variables ($VAR, ${VAR} and
+// Windows-style %VAR%). References to unset variables are left as typed, so
+// names like C:\$Recycle.Bin survive. Relative paths are made absolute later.
 func expandPath(p string) string {
+	p = expandEnv(p)
 	if strings.HasPrefix(p, "~") {
 		if h, err := os.UserHomeDir(); err == nil {
 			return filepath.Join(h, strings.TrimPrefix(p, "~"))
@@ -1501,6 +3195,53 @@ func expandPath(p string) string {
 	return p
 }
 
+// expandEnv replaces $VAR, ${VAR} and %VAR% references to set variables and
+// copies everything else through unchanged, including references to unset ones.
+func expandEnv(p string) string {
+	var b strings.Builder
+	for i := 0; i < len(p); {
+		switch p[i] {
+		case '$':
+			if name, n := dollarRef(p[i:]); name != "" {
+				if v, ok := os.LookupEnv(name); ok {
+					b.WriteString(v)
+					i += n
+					continue
+				}
+			}
+		case '%':
+			// not a variable: keep the first % and look again from the second
+			if j := strings.IndexByte(p[i+1:], '%'); j > 0 {
+				if v, ok := os.LookupEnv(p[i+1 : i+1+j]); ok {
+					b.WriteString(v)
+					i += j + 2
+					continue
+				}
+			}
+		}
+		b.WriteByte(p[i])
+		i++
+	}
+	return b.String()
+}
+
+// dollarRef parses the ${NAME} or $NAME reference at the start of s and returns
+// the name and the length of the reference, or "" when there is none.
+func dollarRef(s string) (string, int) {
+	if strings.HasPrefix(s, "${") {
+		end := strings.IndexByte(s, '}')
+		if end < 0 {
+			return "", 0
+		}
+		return s[2:end], end + 1
+	}
+	n := 1
+	for n < len(s) && (s[n] == '_' || 'a' <= s[n] && s[n] <= 'z' || 'A' <= s[n] && s[n] <= 'Z' || '0' <= s[n] && s[n] <= '9') {
+		n++
+	}
+	return s[1:n], n
+}
+
 func mustNoErr(err error) {
 	if err != nil {
 		fail(err)
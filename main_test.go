@@ -0,0 +1,1287 @@
+package main
+
+import (
+	"bytes"
+	"context"
+	"encoding/csv"
+	"encoding/json"
+	"errors"
+	"fmt"
+	"io"
+	"math"
+	"os"
+	"path/filepath"
+	"runtime"
+	"strings"
+	"sync"
+	"testing"
+	"time"
+)
+
+// writeFile creates path (and its parents) with data and the given modification time.
+func writeFile(t *testing.T, path, data string, mtime time.Time) {
+	t.Helper()
+	if err := os.MkdirAll(filepath.Dir(path), 0o755); err != nil {
+		t.Fatal(err)
+	}
+	if err := os.WriteFile(path, []byte(data), 0o644); err != nil {
+		t.Fatal(err)
+	}
+	if err := os.Chtimes(path, mtime, mtime); err != nil {
+		t.Fatal(err)
+	}
+}
+
+func TestFilterExistingSyncTimes(t *testing.T) {
+	dir := t.TempDir()
+	src, dst := filepath.Join(dir, "src", "a.txt"), filepath.Join(dir, "dst", "a.txt")
+	srcTime := time.Date(2020, 1, 2, 3, 4, 6, 0, time.UTC)
+	writeFile(t, src, "same content", srcTime)
+	writeFile(t, dst, "same content", srcTime.Add(time.Hour))
+	before, err := os.Stat(dst)
+	if err != nil {
+		t.Fatal(err)
+	}
+
+	res := filterExisting([][2]string{{src, dst}}, false, true)
+	if len(res.toCopy) != 0 || res.skipped != 1 {
+		t.Fatalf("toCopy=%v skipped=%d, want nothing to copy and 1 skipped", res.toCopy, res.skipped)
+	}
+	if len(res.timeFixes) != 1 {
+		t.Fatalf("timeFixes=%v, want 1", res.timeFixes)
+	}
+	// filtering alone (as in --dry-run) must not touch the destination
+	if st, _ := os.Stat(dst); !st.ModTime().Equal(before.ModTime()) {
+		t.Fatalf("filterExisting changed the destination mtime")
+	}
+
+	if n := applyTimeFixes(res.timeFixes); n != 1 {
+		t.Fatalf("applyTimeFixes = %d, want 1", n)
+	}
+	after, err := os.Stat(dst)
+	if err != nil {
+		t.Fatal(err)
+	}
+	if !after.ModTime().Equal(srcTime) {
+		t.Errorf("mtime = %v, want %v", after.ModTime(), srcTime)
+	}
+	if !os.SameFile(before, after) {
+		t.Errorf("destination was replaced instead of updated in place")
+	}
+	if b, _ := os.ReadFile(dst); string(b) != "same content" {
+		t.Errorf("content changed: %q", b)
+	}
+}
+
+func TestCopyWithTimeoutAbandonsSlowCopy(t *testing.T) {
+	oldTimeout, oldCopy := fileTimeout, copyFileFunc
+	t.Cleanup(func() { fileTimeout, copyFileFunc = oldTimeout, oldCopy })
+	fileTimeout = 100 * time.Millisecond
+
+	release, returned := make(chan struct{}), make(chan struct{})
+	// a reader stuck on a dead share: writes a little, then blocks until released
+	copyFileFunc = func(ctx context.Context, src, dst string, agg *progressAgg, mu *sync.Mutex, logsCh chan string, interactive bool) error {
+		defer close(returned)
+		if err := os.WriteFile(dst, []byte("partial"), 0o644); err != nil {
+			return err
+		}
+		agg.Add(7)
+		<-release
+		agg.Add(1 << 20)
+		return nil
+	}
+
+	tmp := filepath.Join(t.TempDir(), "a.txt.part")
+	agg := &progressAgg{}
+	err := copyWithTimeout(context.Background(), "src", tmp, agg, &sync.Mutex{}, nil, false)
+	if !errors.Is(err, errFileTimedOut) {
+		t.Fatalf("err = %v, want a timeout", err)
+	}
+	if n := agg.Done(); n != 0 {
+		t.Errorf("agg counts %d bytes of the abandoned copy, want 0", n)
+	}
+
+	close(release)
+	<-returned
+	if n := agg.Done(); n != 0 {
+		t.Errorf("agg counts %d bytes after the abandoned copy finished, want 0", n)
+	}
+	deadline := time.Now().Add(2 * time.Second)
+	for {
+		if _, err := os.Stat(tmp); os.IsNotExist(err) {
+			break
+		}
+		if time.Now().After(deadline) {
+			t.Fatalf("%s was not removed after the abandoned copy returned", tmp)
+		}
+		time.Sleep(10 * time.Millisecond)
+	}
+}
+
+func TestCopyAllFailFastReportsNotAttempted(t *testing.T) {
+	oldNoProgress := noProgress
+	t.Cleanup(func() { noProgress = oldNoProgress })
+	noProgress = true
+
+	dir := t.TempDir()
+	// a file where the destination folder should be makes the first copy fail
+	writeFile(t, filepath.Join(dir, "dst", "blocked"), "", time.Now())
+	pairs := [][2]string{{filepath.Join(dir, "src", "0.txt"), filepath.Join(dir, "dst", "blocked", "0.txt")}}
+	for _, name := range []string{"1.txt", "2.txt", "3.txt", "4.txt"} {
+		pairs = append(pairs, [2]string{filepath.Join(dir, "src", name), filepath.Join(dir, "dst", name)})
+	}
+	for _, p := range pairs {
+		writeFile(t, p[0], "data", time.Now())
+	}
+	manifest := filepath.Join(dir, "manifest.jsonl")
+
+	stats := copyAll(context.Background(), pairs, nil, manifest, nil, 1, true, nil)
+	if stats.errors != 1 || stats.copied != 0 || stats.notAttempted != 4 {
+		t.Fatalf("errors=%d copied=%d notAttempted=%d, want 1, 0, 4", stats.errors, stats.copied, stats.notAttempted)
+	}
+	b, err := os.ReadFile(manifest)
+	if err != nil {
+		t.Fatal(err)
+	}
+	if n := strings.Count(string(b), "\n"); n != len(pairs) {
+		t.Errorf("manifest has %d records, want %d", n, len(pairs))
+	}
+}
+
+func TestNumberedName(t *testing.T) {
+	taken := map[string]bool{}
+	free := func(p string) bool { return !taken[p] }
+	for _, tc := range []struct {
+		path  string
+		taken []string
+		want  string
+	}{
+		{"a/report.txt", nil, "a/report.txt"},
+		{"a/report.txt", []string{"a/report.txt"}, "a/report (1).txt"},
+		{"a/report.txt", []string{"a/report.txt", "a/report (1).txt"}, "a/report (2).txt"},
+		{"a/archive.tar.gz", []string{"a/archive.tar.gz"}, "a/archive.tar (1).gz"},
+		{"a/.bashrc", []string{"a/.bashrc"}, "a/.bashrc (1)"},
+		{"a/Makefile", []string{"a/Makefile"}, "a/Makefile (1)"},
+	} {
+		clear(taken)
+		for _, p := range tc.taken {
+			taken[filepath.FromSlash(p)] = true
+		}
+		if got := numberedName(filepath.FromSlash(tc.path), free); got != filepath.FromSlash(tc.want) {
+			t.Errorf("numberedName(%q) with %v taken = %q, want %q", tc.path, tc.taken, got, tc.want)
+		}
+	}
+}
+
+func TestFilterExistingKeepBothRerun(t *testing.T) {
+	dir := t.TempDir()
+	src, dst := filepath.Join(dir, "src", "a.txt"), filepath.Join(dir, "dst", "a.txt")
+	writeFile(t, src, "new version", time.Now())
+	writeFile(t, dst, "old", time.Now())
+
+	res := filterExisting([][2]string{{src, dst}}, true, false)
+	want := filepath.Join(dir, "dst", "a (1).txt")
+	if len(res.toCopy) != 1 || res.toCopy[0][1] != want {
+		t.Fatalf("first run toCopy=%v, want a copy to %s", res.toCopy, want)
+	}
+	writeFile(t, want, "new version", time.Now())
+
+	// a rerun finds the kept copy instead of adding "a (2).txt"
+	res = filterExisting([][2]string{{src, dst}}, true, false)
+	if len(res.toCopy) != 0 || res.skipped != 1 {
+		t.Fatalf("rerun toCopy=%v skipped=%d, want nothing to copy and 1 skipped", res.toCopy, res.skipped)
+	}
+}
+
+func TestDropDuplicateDestinations(t *testing.T) {
+	plans := [][2]string{
+		{"/a/x.txt", "/dst/x.txt"},
+		{"/a/x.txt", "/dst/x.txt"}, // same file through an overlapping source
+		{"/b/x.txt", "/dst/x.txt"}, // a different file with the same destination
+		{"/a/y.txt", "/dst/y.txt"},
+	}
+	out, clashes := dropDuplicateDestinations(plans)
+	if len(out) != 3 {
+		t.Errorf("kept %d plans, want 3: %v", len(out), out)
+	}
+	if len(clashes) != 1 || clashes[0] != "/dst/x.txt <- /a/x.txt, /b/x.txt" {
+		t.Errorf("clashes = %q, want one for /dst/x.txt", clashes)
+	}
+}
+
+func TestCopyAllReportCSV(t *testing.T) {
+	oldNoProgress := noProgress
+	t.Cleanup(func() { noProgress = oldNoProgress })
+	noProgress = true
+
+	dir := t.TempDir()
+	var pairs [][2]string
+	for _, name := range []string{"a.txt", "b.txt", "c.txt"} {
+		p := [2]string{filepath.Join(dir, "src", name), filepath.Join(dir, "dst", name)}
+		writeFile(t, p[0], "data of "+name, time.Now())
+		pairs = append(pairs, p)
+	}
+	// same size on the destination, so copyOneWithProgress skips it
+	writeFile(t, pairs[2][1], "data of c.txt", time.Now())
+
+	var buf bytes.Buffer
+	report := csv.NewWriter(&buf)
+	if err := report.Write(reportHeader); err != nil {
+		t.Fatal(err)
+	}
+	stats := copyAll(context.Background(), pairs, nil, filepath.Join(dir, "manifest.jsonl"), report, 2, false, nil)
+	report.Flush()
+	if err := report.Error(); err != nil {
+		t.Fatal(err)
+	}
+	if stats.copied != 2 || stats.skipped != 1 {
+		t.Fatalf("copied=%d skipped=%d, want 2 and 1", stats.copied, stats.skipped)
+	}
+
+	rows, err := csv.NewReader(&buf).ReadAll()
+	if err != nil {
+		t.Fatal(err)
+	}
+	if len(rows) != 1+len(pairs) {
+		t.Fatalf("got %d rows, want a header and %d files", len(rows), len(pairs))
+	}
+	statuses := map[string]int{}
+	for i, r := range rows {
+		if len(r) != len(reportHeader) {
+			t.Errorf("row %d has %d columns, want %d", i, len(r), len(reportHeader))
+		}
+		if i > 0 {
+			statuses[r[3]]++
+		}
+	}
+	if statuses["copied"] != 2 || statuses["skipped"] != 1 {
+		t.Errorf("statuses = %v, want 2 copied and 1 skipped", statuses)
+	}
+}
+
+func TestLimitRunAcrossRuns(t *testing.T) {
+	oldNoProgress := noProgress
+	t.Cleanup(func() { noProgress = oldNoProgress })
+	noProgress = true
+
+	dir := t.TempDir()
+	var plans [][2]string
+	for i := 0; i < 10; i++ {
+		name := fmt.Sprintf("%d.txt", i)
+		p := [2]string{filepath.Join(dir, "src", name), filepath.Join(dir, "dst", name)}
+		writeFile(t, p[0], "data", time.Now())
+		plans = append(plans, p)
+	}
+	// what main does on each --resume run with --max-files 3
+	var perRun []int
+	for run := 0; run < 10; run++ {
+		toCopy := filterExisting(plans, false, false).toCopy
+		if len(toCopy) == 0 {
+			break
+		}
+		toCopy = toCopy[:limitRun(toCopy, 3, 0)]
+		stats := copyAll(context.Background(), toCopy, nil, filepath.Join(dir, "manifest.jsonl"), nil, 2, false, nil)
+		perRun = append(perRun, stats.copied)
+	}
+	if fmt.Sprint(perRun) != "[3 3 3 1]" {
+		t.Errorf("copied per run = %v, want [3 3 3 1]", perRun)
+	}
+}
+
+func TestLimitRunOversizedFirstFile(t *testing.T) {
+	dir := t.TempDir()
+	big, small := filepath.Join(dir, "big"), filepath.Join(dir, "small")
+	writeFile(t, big, strings.Repeat("x", 100), time.Now())
+	writeFile(t, small, "x", time.Now())
+	plans := [][2]string{{big, "dst/big"}, {small, "dst/small"}}
+	if n := limitRun(plans, 0, 10); n != 1 {
+		t.Errorf("limitRun = %d, want the oversized first file to be allowed alone", n)
+	}
+	if n := limitRun(plans[1:], 0, 10); n != 1 {
+		t.Errorf("limitRun = %d, want 1", n)
+	}
+}
+
+func TestResolveCaseCollisions(t *testing.T) {
+	dir := t.TempDir()
+	upper, lower := filepath.Join(dir, "dst", "File.txt"), filepath.Join(dir, "dst", "file.txt")
+	plans := func() [][2]string {
+		return [][2]string{{"/src/file.txt", lower}, {"/src/File.txt", upper}, {"/src/other.txt", filepath.Join(dir, "dst", "other.txt")}}
+	}
+
+	out, renamed, clashes := resolveCaseCollisions(plans(), false)
+	if len(out) != 2 || len(renamed) != 0 {
+		t.Fatalf("kept %v, renamed %v; want the later file left out", out, renamed)
+	}
+	if len(clashes) != 1 || clashes[0].path != "/src/file.txt" {
+		t.Fatalf("clashes = %v, want /src/file.txt reported", clashes)
+	}
+	for _, p := range out {
+		if p[0] == "/src/file.txt" {
+			t.Errorf("clashing file still planned: %v", p)
+		}
+	}
+
+	// a differently-cased entry already on the destination is not a free name
+	writeFile(t, filepath.Join(dir, "dst", "FILE (1).txt"), "", time.Now())
+	out, renamed, clashes = resolveCaseCollisions(plans(), true)
+	if len(out) != 3 || len(clashes) != 0 || len(renamed) != 1 {
+		t.Fatalf("kept %v, renamed %v, clashes %v; want all kept and one renamed", out, renamed, clashes)
+	}
+	if want := filepath.Join(dir, "dst", "file (2).txt"); out[0][1] != want {
+		t.Errorf("renamed to %s, want %s", out[0][1], want)
+	}
+}
+
+func TestVerifyPlansTamperedFile(t *testing.T) {
+	dir := t.TempDir()
+	var plans [][2]string
+	for _, name := range []string{"a.txt", "b.txt"} {
+		p := [2]string{filepath.Join(dir, "src", name), filepath.Join(dir, "dst", name)}
+		writeFile(t, p[0], "original", time.Now())
+		writeFile(t, p[1], "original", time.Now())
+		plans = append(plans, p)
+	}
+	// same size, different bytes: only a content comparison catches it
+	writeFile(t, plans[1][1], "tampered", time.Now())
+	plans = append(plans, [2]string{filepath.Join(dir, "src", "a.txt"), filepath.Join(dir, "dst", "missing.txt")})
+
+	vs := verifyPlans(context.Background(), plans, 2, verifyRange{}, nil)
+	if vs.checked != 2 || vs.ok != 1 || vs.missing != 1 {
+		t.Errorf("checked=%d ok=%d missing=%d, want 2, 1, 1", vs.checked, vs.ok, vs.missing)
+	}
+	if len(vs.mismatches) != 1 || vs.mismatches[0].path != plans[1][1] {
+		t.Fatalf("mismatches = %v, want %s", vs.mismatches, plans[1][1])
+	}
+	if vs.mismatches[0].message != "content differs from source" {
+		t.Errorf("message = %q", vs.mismatches[0].message)
+	}
+}
+
+func TestSplitDuplicatesStopsWhenCancelled(t *testing.T) {
+	dir := t.TempDir()
+	var pairs [][2]string
+	for _, name := range []string{"a.txt", "b.txt"} {
+		p := [2]string{filepath.Join(dir, "src", name), filepath.Join(dir, "dst", name)}
+		writeFile(t, p[0], "same", time.Now())
+		pairs = append(pairs, p)
+	}
+	copies, links := splitDuplicates(context.Background(), pairs)
+	if len(copies) != 1 || len(links) != 1 {
+		t.Fatalf("copies=%v links=%v, want one of each", copies, links)
+	}
+
+	ctx, cancel := context.WithCancel(context.Background())
+	cancel()
+	copies, links = splitDuplicates(ctx, pairs)
+	if len(copies) != 2 || len(links) != 0 {
+		t.Errorf("after cancel copies=%v links=%v, want everything copied", copies, links)
+	}
+}
+
+func TestExpandEnv(t *testing.T) {
+	t.Setenv("BACKUP_TEST_DIR", "/data")
+	for _, tc := range []struct{ in, want string }{
+		{"$BACKUP_TEST_DIR/docs", "/data/docs"},
+		{"${BACKUP_TEST_DIR}docs", "/datadocs"},
+		{"%BACKUP_TEST_DIR%\\docs", "/data\\docs"},
+		{"$BACKUP_TEST_UNSET/docs", "$BACKUP_TEST_UNSET/docs"},
+		{"${BACKUP_TEST_UNSET}/docs", "${BACKUP_TEST_UNSET}/docs"},
+		{"%BACKUP_TEST_UNSET%\\docs", "%BACKUP_TEST_UNSET%\\docs"},
+		{"C:\\$Recycle.Bin", "C:\\$Recycle.Bin"},
+		{"100% of %BACKUP_TEST_DIR%", "100% of /data"},
+		{"cost $5 ${", "cost $5 ${"},
+	} {
+		if got := expandEnv(tc.in); got != tc.want {
+			t.Errorf("expandEnv(%q) = %q, want %q", tc.in, got, tc.want)
+		}
+	}
+}
+
+func TestScanSourcesMaxDepthLeavesOutDeeperFolders(t *testing.T) {
+	dir := t.TempDir()
+	src := filepath.Join(dir, "src")
+	writeFile(t, filepath.Join(src, "top.txt"), "x", time.Now())
+	writeFile(t, filepath.Join(src, "sub", "deep.txt"), "x", time.Now())
+
+	opts := scanOptions{maxDepth: 0}
+	scan := scanSources(context.Background(), []string{src}, nil, nil, opts, filepath.Join(dir, "usb"), nil)
+	if len(scan.files) != 1 || filepath.Base(scan.files[0].Path) != "top.txt" {
+		t.Errorf("files = %v, want only top.txt", scan.files)
+	}
+	for _, d := range scan.emptyDirs {
+		if filepath.Base(d) == "sub" {
+			t.Errorf("emptyDirs = %v, want the folder beyond --max-depth left out", scan.emptyDirs)
+		}
+	}
+}
+
+func TestVerifyPlansSizeRange(t *testing.T) {
+	dir := t.TempDir()
+	plan := func(name, src, dst string) [2]string {
+		p := [2]string{filepath.Join(dir, "src", name), filepath.Join(dir, "dst", name)}
+		writeFile(t, p[0], src, time.Now())
+		writeFile(t, p[1], dst, time.Now())
+		return p
+	}
+	plans := [][2]string{
+		plan("small-ok.txt", "abc", "abc"),
+		plan("small-bad.txt", "abc", "xyz"),
+		plan("large-ok.txt", "0123456789", "0123456789"),
+		plan("large-bad.txt", "0123456789", "9876543210"), // not read, so not caught
+	}
+
+	vs := verifyPlans(context.Background(), plans, 2, verifyRange{max: 5}, nil)
+	if vs.checked != 4 || vs.ok != 1 || vs.sizeOnly != 2 || len(vs.mismatches) != 1 {
+		t.Fatalf("checked=%d ok=%d sizeOnly=%d mismatches=%v, want 4, 1, 2 and one mismatch", vs.checked, vs.ok, vs.sizeOnly, vs.mismatches)
+	}
+	if vs.mismatches[0].path != plans[1][1] {
+		t.Errorf("mismatch on %s, want %s", vs.mismatches[0].path, plans[1][1])
+	}
+}
+
+func TestSnapshotSpeedFollowsRecentWindow(t *testing.T) {
+	const mb = 1 << 20
+	start := time.Date(2024, 1, 1, 0, 0, 0, 0, time.UTC)
+	p := &progressAgg{total: 1 << 40, start: start}
+	now := start
+	// run advances the clock by d in steps of poll, transferring rate bytes per second
+	run := func(d, poll time.Duration, rate int64) progressSnapshot {
+		var s progressSnapshot
+		for end := now.Add(d); now.Before(end); {
+			now = now.Add(poll)
+			p.Add(rate * int64(poll) / int64(time.Second))
+			s = p.Snapshot(now)
+		}
+		return s
+	}
+
+	if s := run(time.Minute, 200*time.Millisecond, mb); math.Abs(s.speed-mb) > mb/100 {
+		t.Fatalf("speed = %.0f after a steady minute, want %d", s.speed, mb)
+	}
+	// after one window at the new rate the old one no longer counts
+	if s := run(speedWindow+time.Second, 200*time.Millisecond, 10*mb); math.Abs(s.speed-10*mb) > 10*mb/100 {
+		t.Errorf("speed = %.0f a window after speeding up, want %d", s.speed, 10*mb)
+	}
+	// polled less often than the window: the speed covers the last interval
+	if s := run(3*time.Minute, time.Minute, 2*mb); math.Abs(s.speed-2*mb) > 2*mb/100 {
+		t.Errorf("speed = %.0f with one-minute polls, want %d", s.speed, 2*mb)
+	}
+}
+
+func TestRelativeDestPathSourceFolders(t *testing.T) {
+	root := t.TempDir()
+	a, b, c := filepath.Join(root, "a", "Docs"), filepath.Join(root, "b", "Docs"), filepath.Join(root, "c", "docs")
+	sources := []string{a, b, c}
+	names := sourceFolderNames(sources)
+	for _, tc := range []struct{ src, want string }{
+		{filepath.Join(a, "x.txt"), filepath.Join("Docs", "x.txt")},
+		{filepath.Join(b, "x.txt"), filepath.Join("Docs (2)", "x.txt")},
+		{filepath.Join(c, "sub", "x.txt"), filepath.Join("docs (3)", "sub", "x.txt")},
+	} {
+		if got := relativeDestPath(tc.src, sources, names); got != tc.want {
+			t.Errorf("relativeDestPath(%s) = %s, want %s", tc.src, got, tc.want)
+		}
+	}
+	if got, want := relativeDestPath(filepath.Join(b, "x.txt"), sources, nil), "x.txt"; got != want {
+		t.Errorf("merged: relativeDestPath = %s, want %s", got, want)
+	}
+}
+
+func TestParseSize(t *testing.T) {
+	for _, tc := range []struct {
+		in   string
+		want int64
+		ok   bool
+	}{
+		{"4096", 4096, true},
+		{"512K", 512 << 10, true},
+		{"512kb", 512 << 10, true},
+		{"10MB", 10 << 20, true},
+		{"1.5GiB", 3 << 29, true},
+		{" 2 TB ", 2 << 40, true},
+		{"7B", 7, true},
+		{"10KI", 0, false}, // an I needs the B after it
+		{"5I", 0, false},
+		{"5IB", 0, false},
+		{"-1MB", 0, false},
+		{"inf", 0, false},
+		{"", 0, false},
+		{"MB", 0, false},
+		{"ten", 0, false},
+	} {
+		got, err := parseSize(tc.in)
+		if (err == nil) != tc.ok || got != tc.want {
+			t.Errorf("parseSize(%q) = %d, %v; want %d, ok=%v", tc.in, got, err, tc.want, tc.ok)
+		}
+	}
+}
+
+func TestParseCutoff(t *testing.T) {
+	now := time.Date(2024, 3, 10, 12, 0, 0, 0, time.Local)
+	for _, tc := range []struct {
+		in   string
+		want time.Time
+		ok   bool
+	}{
+		{"2024-01-31", time.Date(2024, 1, 31, 0, 0, 0, 0, time.Local), true},
+		{"30d", now.AddDate(0, 0, -30), true},
+		{"36h", now.Add(-36 * time.Hour), true},
+		{"90m", now.Add(-90 * time.Minute), true},
+		{"0d", now, true},
+		{"-5d", time.Time{}, false},
+		{"-1h", time.Time{}, false},
+		{"2024-13-01", time.Time{}, false},
+		{"yesterday", time.Time{}, false},
+		{"", time.Time{}, false},
+	} {
+		got, err := parseCutoff(tc.in, now)
+		if (err == nil) != tc.ok || !got.Equal(tc.want) {
+			t.Errorf("parseCutoff(%q) = %v, %v; want %v, ok=%v", tc.in, got, err, tc.want, tc.ok)
+		}
+	}
+}
+
+func TestCopyAllLinkedDuplicateBytes(t *testing.T) {
+	oldNoProgress := noProgress
+	t.Cleanup(func() { noProgress = oldNoProgress })
+	noProgress = true
+
+	dir := t.TempDir()
+	data := strings.Repeat("duplicate", 100)
+	a, b := filepath.Join(dir, "src", "a.bin"), filepath.Join(dir, "src", "b.bin")
+	writeFile(t, a, data, time.Now())
+	writeFile(t, b, data, time.Now())
+	dstA, dstB := filepath.Join(dir, "dst", "a.bin"), filepath.Join(dir, "dst", "b.bin")
+
+	links := []hardlink{{src: b, target: dstA, dst: dstB}}
+	stats := copyAll(context.Background(), [][2]string{{a, dstA}}, links, filepath.Join(dir, "manifest.jsonl"), nil, 1, false, nil)
+	if stats.copied != 2 || stats.errors != 0 {
+		t.Fatalf("copied=%d errors=%d, want 2 and 0", stats.copied, stats.errors)
+	}
+	size := int64(len(data))
+	if stats.bytes != 2*size || stats.linkedBytes != size || stats.written() != size {
+		t.Errorf("bytes=%d linked=%d written=%d, want %d, %d, %d", stats.bytes, stats.linkedBytes, stats.written(), 2*size, size, size)
+	}
+	stA, errA := os.Stat(dstA)
+	stB, errB := os.Stat(dstB)
+	if errA != nil || errB != nil || !os.SameFile(stA, stB) {
+		t.Errorf("%s is not a hard link to %s", dstB, dstA)
+	}
+}
+
+func TestCopyOneDurable(t *testing.T) {
+	oldDurable := durable
+	t.Cleanup(func() { durable = oldDurable })
+	durable = true
+
+	dir := t.TempDir()
+	src, dst := filepath.Join(dir, "src", "a.txt"), filepath.Join(dir, "dst", "sub", "a.txt")
+	writeFile(t, src, "flushed to disk", time.Now())
+	status, msg, _ := copyOneWithProgress(context.Background(), src, dst, &progressAgg{}, &sync.Mutex{}, nil, true)
+	if status != "copied" {
+		t.Fatalf("status = %s (%s), want copied", status, msg)
+	}
+	if b, err := os.ReadFile(dst); err != nil || string(b) != "flushed to disk" {
+		t.Errorf("destination = %q, %v", b, err)
+	}
+	if _, err := os.Stat(dst + ".part"); !os.IsNotExist(err) {
+		t.Errorf("%s.part left behind", dst)
+	}
+}
+
+// scriptedReader returns its chunks in turn; a nil chunk is a transient io.EOF.
+// Once the chunks run out it keeps returning io.EOF.
+type scriptedReader struct{ chunks [][]byte }
+
+func (r *scriptedReader) Read(p []byte) (int, error) {
+	if len(r.chunks) == 0 {
+		return 0, io.EOF
+	}
+	c := r.chunks[0]
+	r.chunks = r.chunks[1:]
+	if c == nil {
+		return 0, io.EOF
+	}
+	return copy(p, c), nil
+}
+
+func TestCopyStreamRetriesEarlyEOF(t *testing.T) {
+	in := &scriptedReader{chunks: [][]byte{[]byte("abc"), nil, []byte("def")}}
+	var out bytes.Buffer
+	var progressed int64
+	n, err := copyStream(context.Background(), in, &out, make([]byte, 16), 6, func(n, copied int64) { progressed = copied })
+	if err != nil || n != 6 || out.String() != "abcdef" || progressed != 6 {
+		t.Errorf("copyStream = %d, %v, out %q, progress %d; want all 6 bytes", n, err, out.String(), progressed)
+	}
+}
+
+func TestCopyStreamEOFRetryHonoursCancel(t *testing.T) {
+	ctx, cancel := context.WithCancel(context.Background())
+	time.AfterFunc(50*time.Millisecond, cancel)
+	var out bytes.Buffer
+	start := time.Now()
+	// a source that never delivers the promised bytes keeps hitting the retry wait
+	_, err := copyStream(ctx, &scriptedReader{}, &out, make([]byte, 16), 6, func(n, copied int64) {})
+	if err == nil || err.Error() != "cancelled" {
+		t.Fatalf("err = %v, want cancelled", err)
+	}
+	if d := time.Since(start); d >= eofRetryDelay {
+		t.Errorf("returned after %s, want before the %s retry delay ran out", d, eofRetryDelay)
+	}
+}
+
+func TestVerifyMissingSubdir(t *testing.T) {
+	dir := t.TempDir()
+	missing := filepath.Join(dir, "backup_typo")
+	if err := checkVerifyDest(missing); err == nil {
+		t.Fatalf("checkVerifyDest(%s) = nil, want an error", missing)
+	}
+	if _, err := os.Stat(missing); !os.IsNotExist(err) {
+		t.Errorf("verify created %s", missing)
+	}
+	file := filepath.Join(dir, "file")
+	writeFile(t, file, "", time.Now())
+	if err := checkVerifyDest(file); err == nil {
+		t.Errorf("checkVerifyDest(%s) = nil for a file, want an error", file)
+	}
+	if err := checkVerifyDest(dir); err != nil {
+		t.Errorf("checkVerifyDest(%s) = %v", dir, err)
+	}
+
+	// every planned file is then reported missing, which exits with exitVerifyMismatch
+	src := filepath.Join(dir, "src", "a.txt")
+	writeFile(t, src, "data", time.Now())
+	vs := verifyPlans(context.Background(), [][2]string{{src, filepath.Join(missing, "a.txt")}}, 1, verifyRange{}, nil)
+	if vs.missing != 1 || vs.checked != 0 {
+		t.Errorf("missing=%d checked=%d, want 1 and 0", vs.missing, vs.checked)
+	}
+}
+
+func TestDestFoldsCase(t *testing.T) {
+	dir := t.TempDir()
+	// the probe follows whatever the test filesystem does; it must agree with a
+	// direct check and leave nothing behind
+	writeFile(t, filepath.Join(dir, "probe"), "", time.Now())
+	_, err := os.Stat(filepath.Join(dir, "PROBE"))
+	if err := os.Remove(filepath.Join(dir, "probe")); err != nil {
+		t.Fatal(err)
+	}
+	if got, want := destFoldsCase(dir), err == nil; got != want {
+		t.Errorf("destFoldsCase = %v, want %v", got, want)
+	}
+	if entries, _ := os.ReadDir(dir); len(entries) != 0 {
+		t.Errorf("probe left %v behind", entries)
+	}
+}
+
+func TestCopyAllFailFastInFlightCopyNotAttempted(t *testing.T) {
+	oldNoProgress, oldCopy := noProgress, copyFileFunc
+	t.Cleanup(func() { noProgress, copyFileFunc = oldNoProgress, oldCopy })
+	noProgress = true
+	// slow.txt is still copying when the other worker's failure stops the run
+	copyFileFunc = func(ctx context.Context, src, dst string, agg *progressAgg, mu *sync.Mutex, logsCh chan string, interactive bool) error {
+		if filepath.Base(src) == "slow.txt" {
+			<-ctx.Done()
+			return fmt.Errorf("cancelled")
+		}
+		return oldCopy(ctx, src, dst, agg, mu, logsCh, interactive)
+	}
+
+	dir := t.TempDir()
+	writeFile(t, filepath.Join(dir, "dst", "blocked"), "", time.Now())
+	pairs := [][2]string{
+		{filepath.Join(dir, "src", "slow.txt"), filepath.Join(dir, "dst", "slow.txt")},
+		{filepath.Join(dir, "src", "fail.txt"), filepath.Join(dir, "dst", "blocked", "fail.txt")},
+	}
+	for _, name := range []string{"1.txt", "2.txt", "3.txt"} {
+		pairs = append(pairs, [2]string{filepath.Join(dir, "src", name), filepath.Join(dir, "dst", name)})
+	}
+	for _, p := range pairs {
+		writeFile(t, p[0], "data", time.Now())
+	}
+
+	stats := copyAll(context.Background(), pairs, nil, filepath.Join(dir, "manifest.jsonl"), nil, 2, true, nil)
+	if stats.errors != 1 || stats.copied != 0 || stats.notAttempted != 4 {
+		t.Fatalf("errors=%d copied=%d notAttempted=%d, want 1, 0, 4", stats.errors, stats.copied, stats.notAttempted)
+	}
+	if len(stats.failures) != 1 || filepath.Base(stats.failures[0].path) != "fail.txt" {
+		t.Errorf("failures = %v, want only fail.txt", stats.failures)
+	}
+}
+
+func TestCopyWithTimeoutAbandonsCancelledCopy(t *testing.T) {
+	oldTimeout, oldCopy := fileTimeout, copyFileFunc
+	t.Cleanup(func() { fileTimeout, copyFileFunc = oldTimeout, oldCopy })
+	fileTimeout = time.Hour
+
+	release, returned := make(chan struct{}), make(chan struct{})
+	copyFileFunc = func(ctx context.Context, src, dst string, agg *progressAgg, mu *sync.Mutex, logsCh chan string, interactive bool) error {
+		defer close(returned)
+		if err := os.WriteFile(dst, []byte("partial"), 0o644); err != nil {
+			return err
+		}
+		agg.Add(7)
+		<-release // stuck in a read that ignores ctx
+		agg.Add(1 << 20)
+		return nil
+	}
+
+	tmp := filepath.Join(t.TempDir(), "a.txt.part")
+	agg := &progressAgg{}
+	ctx, cancel := context.WithCancel(context.Background())
+	time.AfterFunc(50*time.Millisecond, cancel)
+	err := copyWithTimeout(ctx, "src", tmp, agg, &sync.Mutex{}, nil, false)
+	var abandoned *abandonedError
+	if !errors.As(err, &abandoned) || err.Error() != "cancelled" {
+		t.Fatalf("err = %v, want an abandoned cancelled copy", err)
+	}
+
+	close(release)
+	<-returned
+	if n := agg.Done(); n != 0 {
+		t.Errorf("agg counts %d bytes of the cancelled copy, want 0", n)
+	}
+	deadline := time.Now().Add(2 * time.Second)
+	for {
+		if _, err := os.Stat(tmp); os.IsNotExist(err) {
+			break
+		}
+		if time.Now().After(deadline) {
+			t.Fatalf("%s was not removed after the cancelled copy returned", tmp)
+		}
+		time.Sleep(10 * time.Millisecond)
+	}
+}
+
+func TestEmptyFilesJob(t *testing.T) {
+	oldNoProgress := noProgress
+	t.Cleanup(func() { noProgress = oldNoProgress })
+	noProgress = true
+
+	dir := t.TempDir()
+	var files []FileInfoRec
+	for _, name := range []string{"a", "b.txt", "sub/c.log"} {
+		src := filepath.Join(dir, "src", filepath.FromSlash(name))
+		writeFile(t, src, "", time.Now())
+		files = append(files, FileInfoRec{Path: src, Size: 0, Priority: 1})
+	}
+	selected, used := selectFiles(files, 0, "count")
+	if len(selected) != len(files) || used != 0 {
+		t.Fatalf("selected %d files using %d bytes, want all %d using 0", len(selected), used, len(files))
+	}
+
+	var pairs [][2]string
+	for _, f := range selected {
+		pairs = append(pairs, [2]string{f.Path, filepath.Join(dir, "dst", relativeDestPath(f.Path, []string{filepath.Join(dir, "src")}, nil))})
+	}
+	stats := copyAll(context.Background(), pairs, nil, filepath.Join(dir, "manifest.jsonl"), nil, 2, false, nil)
+	if stats.copied != len(pairs) || stats.errors != 0 {
+		t.Fatalf("copied=%d errors=%d, want %d and 0", stats.copied, stats.errors, len(pairs))
+	}
+	for _, p := range pairs {
+		if st, err := os.Stat(p[1]); err != nil || st.Size() != 0 {
+			t.Errorf("%s not created empty: %v", p[1], err)
+		}
+	}
+	// copyAll's aggregator for this job: no bytes to transfer
+	agg := &progressAgg{total: stats.bytes, files: len(pairs), filesDone: int64(len(pairs)), start: time.Now()}
+	if s := agg.Snapshot(time.Now()); s.percent != 100 {
+		t.Errorf("percent = %.1f, want 100", s.percent)
+	}
+}
+
+func TestValidateSubdir(t *testing.T) {
+	for _, tc := range []struct {
+		in string
+		ok bool
+	}{
+		{"backup_20240101", true},
+		{"backups/2024", true},
+		{`backups\2024`, true},
+		{"./weekly", true},
+		{"con.d", false}, // device names are reserved with any extension
+		{"CON", false},
+		{"con", false},
+		{"nul.txt", false},
+		{"backups/COM1", false},
+		{"lpt9.log", false},
+		{"COM0", true},
+		{"console", true},
+		{"a:b", false},
+		{"what?", false},
+		{`say "hi"`, false},
+		{"a|b", false},
+		{"tab\there", false},
+		{"trailing.", false},
+		{"trailing ", false},
+		{"backups./2024", false},
+	} {
+		if err := validateSubdir(tc.in); (err == nil) != tc.ok {
+			t.Errorf("validateSubdir(%q) = %v, want ok=%v", tc.in, err, tc.ok)
+		}
+	}
+}
+
+func TestIsWithinRoot(t *testing.T) {
+	root := filepath.FromSlash("/usb/backup")
+	for _, tc := range []struct {
+		p    string
+		want bool
+	}{
+		{filepath.Join(root, "a.txt"), true},
+		{filepath.Join(root, "docs", "a.txt"), true},
+		{root + string(filepath.Separator) + filepath.FromSlash("../escape"), false},
+		{root + string(filepath.Separator) + filepath.FromSlash("docs/../../escape"), false},
+		{root + string(filepath.Separator) + filepath.FromSlash("docs/../a.txt"), true},
+		{root, false}, // the root itself is not a file below it
+		{filepath.FromSlash("/usb/backup2/a.txt"), false},
+		{filepath.FromSlash("/etc/passwd"), false},                     // an absolute path as such...
+		{filepath.Join(root, filepath.FromSlash("/etc/passwd")), true}, // ...stays inside once joined
+	} {
+		if got := isWithinRoot(root, tc.p); got != tc.want {
+			t.Errorf("isWithinRoot(%q, %q) = %v, want %v", root, tc.p, got, tc.want)
+		}
+	}
+}
+
+func TestIsWithinRootSymlinkedRoot(t *testing.T) {
+	dir := t.TempDir()
+	target, link := filepath.Join(dir, "target"), filepath.Join(dir, "link")
+	if err := os.MkdirAll(target, 0o755); err != nil {
+		t.Fatal(err)
+	}
+	if err := os.Symlink(target, link); err != nil {
+		t.Skipf("cannot create symlinks here: %v", err)
+	}
+	// the check is lexical: paths are judged against the root as given
+	if !isWithinRoot(link, filepath.Join(link, "a.txt")) {
+		t.Errorf("a file below the linked root is not within it")
+	}
+	if isWithinRoot(link, filepath.Join(link, "..", "target", "a.txt")) {
+		t.Errorf("a path leaving the linked root by name counts as within it")
+	}
+	// resolving both sides, as the USB check for sources does, agrees
+	realRoot, err := filepath.EvalSymlinks(link)
+	if err != nil {
+		t.Fatal(err)
+	}
+	realTarget, err := filepath.EvalSymlinks(target)
+	if err != nil {
+		t.Fatal(err)
+	}
+	if !isWithinRoot(realRoot, filepath.Join(realTarget, "a.txt")) {
+		t.Errorf("resolved root %s does not contain %s", realRoot, filepath.Join(realTarget, "a.txt"))
+	}
+}
+
+func TestMkdirErrMessageNamesBlockingFile(t *testing.T) {
+	dir := t.TempDir()
+	blocker := filepath.Join(dir, "a")
+	writeFile(t, blocker, "not a folder", time.Now())
+	target := filepath.Join(blocker, "b", "c")
+	err := os.MkdirAll(target, 0o755)
+	if err == nil {
+		t.Fatalf("MkdirAll(%s) succeeded through a file", target)
+	}
+	msg := mkdirErrMessage(target, err)
+	if !strings.Contains(msg, "a file is in the way") || !strings.HasSuffix(msg, blocker) {
+		t.Errorf("message = %q, want it to name %s", msg, blocker)
+	}
+}
+
+func TestCopyAllSourceRemovedAfterPlanning(t *testing.T) {
+	oldNoProgress := noProgress
+	t.Cleanup(func() { noProgress = oldNoProgress })
+	noProgress = true
+
+	dir := t.TempDir()
+	kept, gone := filepath.Join(dir, "src", "kept.txt"), filepath.Join(dir, "src", "gone.txt")
+	writeFile(t, kept, "data", time.Now())
+	writeFile(t, gone, "data", time.Now())
+	pairs := [][2]string{{kept, filepath.Join(dir, "dst", "kept.txt")}, {gone, filepath.Join(dir, "dst", "gone.txt")}}
+	// deleted between the scan and the copy
+	if err := os.Remove(gone); err != nil {
+		t.Fatal(err)
+	}
+
+	stats := copyAll(context.Background(), pairs, nil, filepath.Join(dir, "manifest.jsonl"), nil, 1, false, nil)
+	if stats.copied != 1 || stats.errors != 0 || stats.skipped != 1 || stats.skipReasons[skipRemoved] != 1 {
+		t.Errorf("copied=%d errors=%d skipped=%d reasons=%v, want 1 copied and 1 skipped as %q", stats.copied, stats.errors, stats.skipped, stats.skipReasons, skipRemoved)
+	}
+	if _, err := os.Stat(pairs[1][1] + ".part"); !os.IsNotExist(err) {
+		t.Errorf("%s.part left behind", pairs[1][1])
+	}
+}
+
+func TestFilterExistingCompareContent(t *testing.T) {
+	oldCompare := compareContent
+	t.Cleanup(func() { compareContent = oldCompare })
+	compareContent = true
+
+	dir := t.TempDir()
+	same := [2]string{filepath.Join(dir, "src", "same.txt"), filepath.Join(dir, "dst", "same.txt")}
+	differs := [2]string{filepath.Join(dir, "src", "differs.txt"), filepath.Join(dir, "dst", "differs.txt")}
+	writeFile(t, same[0], "identical", time.Now())
+	writeFile(t, same[1], "identical", time.Now())
+	writeFile(t, differs[0], "version 2", time.Now())
+	writeFile(t, differs[1], "version 1", time.Now()) // same size, other bytes
+
+	res := filterExisting([][2]string{same, differs}, false, false)
+	if res.skipped != 1 {
+		t.Errorf("skipped = %d, want the identical file skipped", res.skipped)
+	}
+	if len(res.toCopy) != 1 || res.toCopy[0] != differs {
+		t.Errorf("toCopy = %v, want only %v recopied", res.toCopy, differs)
+	}
+
+	// without --compare-content the size alone decides
+	compareContent = false
+	if res := filterExisting([][2]string{same, differs}, false, false); res.skipped != 2 {
+		t.Errorf("without --compare-content skipped = %d, want 2", res.skipped)
+	}
+}
+
+func TestFlattenPlansNumbersEqualNames(t *testing.T) {
+	dst := filepath.FromSlash("/usb/backup")
+	plans := [][2]string{
+		{filepath.FromSlash("/src/b/name.txt"), ""},
+		{filepath.FromSlash("/src/a/name.txt"), ""},
+		{filepath.FromSlash("/src/a/other.txt"), ""},
+	}
+	flattenPlans(plans, dst)
+	// names are handed out in source path order, so /src/a comes first
+	want := []string{filepath.Join(dst, "name (1).txt"), filepath.Join(dst, "name.txt"), filepath.Join(dst, "other.txt")}
+	for i, p := range plans {
+		if p[1] != want[i] {
+			t.Errorf("%s -> %s, want %s", p[0], p[1], want[i])
+		}
+	}
+}
+
+func TestRestoreDirTimesAfterCopy(t *testing.T) {
+	oldNoProgress := noProgress
+	t.Cleanup(func() { noProgress = oldNoProgress })
+	noProgress = true
+
+	dir := t.TempDir()
+	srcRoot, destDir := filepath.Join(dir, "src"), filepath.Join(dir, "dst")
+	pairs := [][2]string{
+		{filepath.Join(srcRoot, "docs", "a.txt"), filepath.Join(destDir, "docs", "a.txt")},
+		{filepath.Join(srcRoot, "docs", "sub", "b.txt"), filepath.Join(destDir, "docs", "sub", "b.txt")},
+	}
+	for _, p := range pairs {
+		writeFile(t, p[0], "data", time.Now())
+	}
+	docsTime := time.Date(2019, 5, 6, 7, 8, 10, 0, time.UTC)
+	subTime := time.Date(2020, 5, 6, 7, 8, 10, 0, time.UTC)
+	for d, mt := range map[string]time.Time{filepath.Join(srcRoot, "docs"): docsTime, filepath.Join(srcRoot, "docs", "sub"): subTime} {
+		if err := os.Chtimes(d, mt, mt); err != nil {
+			t.Fatal(err)
+		}
+	}
+
+	copyAll(context.Background(), pairs, nil, filepath.Join(dir, "manifest.jsonl"), nil, 1, false, nil)
+	dirPairs := make([][2]string, 0, len(pairs))
+	for _, p := range pairs {
+		dirPairs = append(dirPairs, [2]string{filepath.Dir(p[0]), filepath.Dir(p[1])})
+	}
+	restoreDirTimes(dirPairs, destDir)
+
+	for d, want := range map[string]time.Time{filepath.Join(destDir, "docs"): docsTime, filepath.Join(destDir, "docs", "sub"): subTime} {
+		st, err := os.Stat(d)
+		if err != nil {
+			t.Fatal(err)
+		}
+		if !st.ModTime().Equal(want) {
+			t.Errorf("%s mtime = %v, want %v", d, st.ModTime(), want)
+		}
+	}
+}
+
+func TestWriteJSONSummaryRoundTrip(t *testing.T) {
+	stats := copyStats{copied: 3, skipped: 2, skipReasons: map[string]int{skipExists: 2}, errors: 1, notAttempted: 4, bytes: 300, linkedBytes: 100, elapsed: 1500 * time.Millisecond,
+		failures: []copyFailure{{path: "/src/bad.txt", message: "permission denied"}}}
+	var buf bytes.Buffer
+	writeJSONSummary(&buf, runSummary{
+		Copied: stats.copied, Skipped: stats.skipped, SkipReasons: stats.skipReasons, Errors: stats.errors, NotAttempted: stats.notAttempted,
+		Bytes: stats.bytes, BytesWritten: stats.written(), ElapsedSeconds: stats.elapsed.Seconds(), Failures: summaryFailures(stats.failures),
+	})
+
+	var fields map[string]json.RawMessage
+	if err := json.Unmarshal(buf.Bytes(), &fields); err != nil {
+		t.Fatalf("summary is not JSON: %v\n%s", err, buf.String())
+	}
+	for _, k := range []string{"copied", "skipped", "skip_reasons", "errors", "not_attempted", "bytes", "bytes_written", "elapsed_seconds", "failures"} {
+		if _, ok := fields[k]; !ok {
+			t.Errorf("summary has no %q field", k)
+		}
+	}
+	for _, k := range []string{"verify", "plan"} {
+		if _, ok := fields[k]; ok {
+			t.Errorf("copy summary has a %q field", k)
+		}
+	}
+	var got runSummary
+	if err := json.Unmarshal(buf.Bytes(), &got); err != nil {
+		t.Fatal(err)
+	}
+	if got.Copied != 3 || got.BytesWritten != 200 || got.ElapsedSeconds != 1.5 || len(got.Failures) != 1 || got.Failures[0].Path != "/src/bad.txt" {
+		t.Errorf("round trip = %+v", got)
+	}
+
+	buf.Reset()
+	writeJSONSummary(&buf, runSummary{Verify: &verifySummary{Checked: 2, OK: 1, Mismatches: []summaryFailure{{Path: "/dst/x", Message: "content differs from source"}}}})
+	got = runSummary{}
+	if err := json.Unmarshal(buf.Bytes(), &got); err != nil {
+		t.Fatal(err)
+	}
+	if got.Verify == nil || got.Verify.Checked != 2 || len(got.Verify.Mismatches) != 1 {
+		t.Errorf("verify round trip = %+v", got.Verify)
+	}
+}
+
+func TestVerifyPlansCancelledLeavesUnchecked(t *testing.T) {
+	src, dst := t.TempDir(), t.TempDir()
+	mtime := time.Now().Add(-time.Hour)
+	var plans [][2]string
+	for i := 0; i < 5; i++ {
+		name := fmt.Sprintf("f%d.txt", i)
+		writeFile(t, filepath.Join(src, name), "same", mtime)
+		writeFile(t, filepath.Join(dst, name), "same", mtime)
+		plans = append(plans, [2]string{filepath.Join(src, name), filepath.Join(dst, name)})
+	}
+	ctx, cancel := context.WithCancel(context.Background())
+	cancel()
+
+	vs := verifyPlans(ctx, plans, 2, verifyRange{}, nil)
+	if vs.unchecked != len(plans) || vs.checked != 0 {
+		t.Fatalf("checked=%d unchecked=%d, want 0 and %d", vs.checked, vs.unchecked, len(plans))
+	}
+	if got := vs.exitCode(); got != exitCopyErrors {
+		t.Errorf("exit code = %d, want %d", got, exitCopyErrors)
+	}
+	vs.missing = 1
+	if got := vs.exitCode(); got != exitVerifyMismatch {
+		t.Errorf("exit code with a missing file = %d, want %d", got, exitVerifyMismatch)
+	}
+	if got := (verifyStats{checked: 5, ok: 5}).exitCode(); got != 0 {
+		t.Errorf("clean verify exit code = %d, want 0", got)
+	}
+}
+
+func TestSelectFilesEqualSizesSortedByPath(t *testing.T) {
+	var files []FileInfoRec
+	for _, i := range []int{7, 2, 9, 0, 5, 3, 8, 1, 6, 4, 12, 10, 15, 11, 14, 13} {
+		files = append(files, FileInfoRec{Path: fmt.Sprintf("/src/f%02d", i), Size: 10, Priority: 1})
+	}
+	for _, objective := range []string{"count", "space"} {
+		got, used := selectFiles(files, math.MaxInt64, objective)
+		if len(got) != len(files) || used != int64(10*len(files)) {
+			t.Fatalf("%s: selected %d files (%d bytes), want all", objective, len(got), used)
+		}
+		for i, f := range got {
+			if want := fmt.Sprintf("/src/f%02d", i); f.Path != want {
+				t.Errorf("%s: selected[%d] = %s, want %s", objective, i, f.Path, want)
+			}
+		}
+	}
+	// with room for only some of them, the first paths win
+	got, _ := selectFiles(files, 30, "count")
+	if len(got) != 3 || got[0].Path != "/src/f00" || got[2].Path != "/src/f02" {
+		t.Errorf("capacity 30 selected %v", got)
+	}
+}
+
+// scannedFiles returns the scanned file paths relative to src, with "/" separators.
+func scannedFiles(t *testing.T, src string, scan scanResult) map[string]bool {
+	t.Helper()
+	got := map[string]bool{}
+	for _, f := range scan.files {
+		rel, err := filepath.Rel(src, f.Path)
+		if err != nil {
+			t.Fatal(err)
+		}
+		got[filepath.ToSlash(rel)] = true
+	}
+	return got
+}
+
+func TestScanSourcesExcludeMarker(t *testing.T) {
+	dir := t.TempDir()
+	src := filepath.Join(dir, "src")
+	writeFile(t, filepath.Join(src, "keep", "a.txt"), "x", time.Now())
+	writeFile(t, filepath.Join(src, "skip", ".nobackup"), "", time.Now())
+	writeFile(t, filepath.Join(src, "skip", "b.txt"), "x", time.Now())
+	writeFile(t, filepath.Join(src, "skip", "sub", "c.txt"), "x", time.Now())
+	// a folder with the marker's name is not a marker
+	writeFile(t, filepath.Join(src, "folder", ".nobackup", "d.txt"), "x", time.Now())
+	// nor is a file whose name differs only by case
+	writeFile(t, filepath.Join(src, "cased", ".NOBACKUP"), "", time.Now())
+	writeFile(t, filepath.Join(src, "cased", "e.txt"), "x", time.Now())
+
+	scan := scanSources(context.Background(), []string{src}, nil, nil, scanOptions{maxDepth: -1, marker: ".nobackup"}, filepath.Join(dir, "usb"), nil)
+	got := scannedFiles(t, src, scan)
+	for _, want := range []string{"keep/a.txt", "folder/.nobackup/d.txt", "cased/.NOBACKUP", "cased/e.txt"} {
+		if !got[want] {
+			t.Errorf("%s not scanned; got %v", want, got)
+		}
+	}
+	for _, skipped := range []string{"skip/.nobackup", "skip/b.txt", "skip/sub/c.txt"} {
+		if got[skipped] {
+			t.Errorf("%s scanned inside a marked folder", skipped)
+		}
+	}
+}
+
+func TestScanSourcesReportsUnreadableFolder(t *testing.T) {
+	if runtime.GOOS == "windows" || os.Geteuid() == 0 {
+		t.Skip("folder permissions are not enforced here")
+	}
+	dir := t.TempDir()
+	src := filepath.Join(dir, "src")
+	writeFile(t, filepath.Join(src, "ok.txt"), "x", time.Now())
+	locked := filepath.Join(src, "locked")
+	writeFile(t, filepath.Join(locked, "secret.txt"), "x", time.Now())
+	if err := os.Chmod(locked, 0); err != nil {
+		t.Fatal(err)
+	}
+	t.Cleanup(func() { os.Chmod(locked, 0o755) })
+
+	scan := scanSources(context.Background(), []string{src}, nil, nil, scanOptions{maxDepth: -1}, filepath.Join(dir, "usb"), nil)
+	if got := scannedFiles(t, src, scan); len(got) != 1 || !got["ok.txt"] {
+		t.Errorf("files = %v, want only ok.txt", got)
+	}
+	if len(scan.unreadable) != 1 || scan.unreadable[0].path != locked || scan.unreadable[0].message == "" {
+		t.Errorf("unreadable = %+v, want %s with a reason", scan.unreadable, locked)
+	}
+}
+
+func TestScanSourcesNoHidden(t *testing.T) {
+	if runtime.GOOS == "windows" {
+		t.Skip("hidden files are marked by attribute on Windows, not by a leading dot")
+	}
+	dir := t.TempDir()
+	src := filepath.Join(dir, "src")
+	writeFile(t, filepath.Join(src, "visible.txt"), "x", time.Now())
+	writeFile(t, filepath.Join(src, ".secret.txt"), "x", time.Now())
+	writeFile(t, filepath.Join(src, ".config", "app.txt"), "x", time.Now())
+
+	for _, tc := range []struct {
+		skipHidden bool
+		want       int
+	}{{false, 3}, {true, 1}} {
+		scan := scanSources(context.Background(), []string{src}, nil, nil, scanOptions{maxDepth: -1, skipHidden: tc.skipHidden}, filepath.Join(dir, "usb"), nil)
+		got := scannedFiles(t, src, scan)
+		if len(got) != tc.want || !got["visible.txt"] {
+			t.Errorf("skipHidden=%v: files = %v, want %d including visible.txt", tc.skipHidden, got, tc.want)
+		}
+	}
+}
+
+func TestSkipReasonBuckets(t *testing.T) {
+	oldNoProgress := noProgress
+	t.Cleanup(func() { noProgress = oldNoProgress })
+	noProgress = true
+
+	dir := t.TempDir()
+	src, dst := filepath.Join(dir, "src"), filepath.Join(dir, "dst")
+	now := time.Now()
+	writeFile(t, filepath.Join(src, "big.txt"), strings.Repeat("x", 100), now.Add(-time.Hour))
+	writeFile(t, filepath.Join(src, "old.txt"), "old", now.Add(-72*time.Hour))
+	writeFile(t, filepath.Join(src, "exists.txt"), "same", now.Add(-time.Hour))
+	writeFile(t, filepath.Join(dst, "exists.txt"), "same", now.Add(-time.Hour))
+	writeFile(t, filepath.Join(src, "gone.txt"), "gone", now.Add(-time.Hour))
+	writeFile(t, filepath.Join(src, "new.txt"), "new", now.Add(-time.Hour))
+
+	opts := scanOptions{maxDepth: -1, maxSize: 10, newerThan: now.Add(-24 * time.Hour)}
+	scan := scanSources(context.Background(), []string{src}, nil, nil, opts, filepath.Join(dir, "usb"), nil)
+	var plans [][2]string
+	for _, f := range scan.files {
+		plans = append(plans, [2]string{f.Path, filepath.Join(dst, filepath.Base(f.Path))})
+	}
+	existing := filterExisting(plans, false, false)
+	if err := os.Remove(filepath.Join(src, "gone.txt")); err != nil {
+		t.Fatal(err)
+	}
+
+	stats := copyAll(context.Background(), existing.toCopy, nil, filepath.Join(dir, "manifest.jsonl"), nil, 1, false, nil)
+	stats.addSkipped(skipExists, existing.skipped)
+	for reason, n := range scan.filtered {
+		stats.addSkipped(reason, n)
+	}
+
+	want := map[string]int{skipExists: 1, skipSizeFilter: 1, skipDateFilter: 1, skipRemoved: 1}
+	if stats.copied != 1 || stats.skipped != 4 || len(stats.skipReasons) != len(want) {
+		t.Fatalf("copied=%d skipped=%d reasons=%v, want 1 copied and %v", stats.copied, stats.skipped, stats.skipReasons, want)
+	}
+	for reason, n := range want {
+		if stats.skipReasons[reason] != n {
+			t.Errorf("skipped (%s) = %d, want %d", reason, stats.skipReasons[reason], n)
+		}
+	}
+}
+
+func TestSnapshotMostlySkippedJobReaches100(t *testing.T) {
+	const files, size = 20, 1000
+	start := time.Now()
+	// copyAll's accounting: 19 skipped files are passed, one is copied
+	p := &progressAgg{total: files * size, files: files, start: start}
+	for i := 0; i < files-1; i++ {
+		p.Pass(size)
+		p.FileDone()
+	}
+	if s := p.Snapshot(start.Add(time.Second)); s.percent != 95 {
+		t.Errorf("percent = %.1f with one file left, want 95", s.percent)
+	}
+	p.Add(size)
+	p.FileDone()
+	s := p.Snapshot(start.Add(2 * time.Second))
+	if s.percent != 100 || s.filesDone != files {
+		t.Errorf("percent = %.1f, files %d/%d at the end, want 100 and all files", s.percent, s.filesDone, s.files)
+	}
+	if s.done != size {
+		t.Errorf("done = %d, want only the copied %d bytes (skipped bytes are not transferred)", s.done, size)
+	}
+}
+
+func TestPrintTotalLinesUntilStopped(t *testing.T) {
+	oldStdout, oldInterval := stdout, progressInterval
+	t.Cleanup(func() { stdout, progressInterval = oldStdout, oldInterval })
+	out, err := os.CreateTemp(t.TempDir(), "stdout")
+	if err != nil {
+		t.Fatal(err)
+	}
+	defer out.Close()
+	stdout, progressInterval = out, 5*time.Millisecond
+
+	agg := &progressAgg{total: 100, files: 2, start: time.Now()}
+	agg.Add(50)
+	agg.FileDone()
+	var mu sync.Mutex
+	stop := make(chan struct{})
+	done := make(chan struct{})
+	go func() {
+		printTotalLines(agg, &mu, stop)
+		close(done)
+	}()
+	time.Sleep(60 * time.Millisecond)
+	close(stop)
+	<-done
+
+	data, err := os.ReadFile(out.Name())
+	if err != nil {
+		t.Fatal(err)
+	}
+	lines := strings.Split(strings.TrimSpace(string(data)), "\n")
+	if len(data) == 0 || len(lines) > 13 {
+		t.Fatalf("%d lines in 60ms at a 5ms interval:\n%s", len(lines), data)
+	}
+	if !strings.HasPrefix(lines[0], "[TOTAL] ") || !strings.Contains(lines[0], "(50.0%) | 1/2 files") {
+		t.Errorf("line = %q, want a [TOTAL] line at 50%% with 1/2 files", lines[0])
+	}
+	time.Sleep(20 * time.Millisecond)
+	if after, _ := os.ReadFile(out.Name()); len(after) != len(data) {
+		t.Errorf("lines printed after stop")
+	}
+}
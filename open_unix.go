@@ -32,3 +32,13 @@ func openFileSequentialWrite(path string, perm fs.FileMode) (*os.File, error) {
 	_ = unix.Fadvise(fd, 0, 0, unix.FADV_SEQUENTIAL)
 	return f, nil
 }
+
+// isFileInUse is always false here: POSIX has no mandatory share modes, so a
+// file open in another process can still be read.
+func isFileInUse(err error) bool {
+	return false
+}
+
+// setCreationTime is a no-op: Linux keeps a birth time (statx) but offers no
+// way to set it.
+func setCreationTime(f *os.File, src fs.FileInfo) {}
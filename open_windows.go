@@ -3,14 +3,17 @@
 package main
 
 import (
+    "errors"
     "io/fs"
     "os"
+    "strings"
+    "syscall"
     "golang.org/x/sys/windows"
 )
 
 // openFileSequentialRead opens file with FILE_FLAG_SEQUENTIAL_SCAN for better cache behavior.
 func openFileSequentialRead(path string) (*os.File, error) {
-    p, err := windows.UTF16PtrFromString(path)
+    p, err := windows.UTF16PtrFromString(longPath(path))
     if err != nil {
         return nil, err
     }
@@ -35,7 +38,7 @@ func openFileSequentialWrite(path string, perm fs.FileMode) (*os.File, error) {
     if err := os.MkdirAll(filepathDir(path), 0o755); err != nil {
         return nil, err
     }
-    p, err := windows.UTF16PtrFromString(path)
+    p, err := windows.UTF16PtrFromString(longPath(path))
     if err != nil {
         return nil, err
     }
@@ -57,6 +60,23 @@ func openFileSequentialWrite(path string, perm fs.FileMode) (*os.File, error) {
     return f, nil
 }
 
+// isFileInUse reports whether err is a sharing or lock violation, i.e. another
+// process (a database, Outlook, ...) holds the file open exclusively.
+func isFileInUse(err error) bool {
+    return errors.Is(err, windows.ERROR_SHARING_VIOLATION) || errors.Is(err, windows.ERROR_LOCK_VIOLATION)
+}
+
+// setCreationTime gives f the creation time of src (best effort); backups should
+// not show every file as created on the day of the backup.
+func setCreationTime(f *os.File, src fs.FileInfo) {
+    d, ok := src.Sys().(*syscall.Win32FileAttributeData)
+    if !ok {
+        return
+    }
+    ct := windows.Filetime{LowDateTime: d.CreationTime.LowDateTime, HighDateTime: d.CreationTime.HighDateTime}
+    _ = windows.SetFileTime(windows.Handle(f.Fd()), &ct, nil, nil)
+}
+
 // filepathDir avoids importing path/filepath here to keep imports minimal
 func filepathDir(p string) string {
     // Use windows APIs to find last separator
@@ -73,3 +93,22 @@ func filepathDir(p string) string {
     }
     return string(s[:idx])
 }
+
+// longPath rewrites long absolute paths into the \\?\ extended-length form so the
+// raw CreateFile calls above are not limited by MAX_PATH (the os package does the
+// same internally for its own calls). UNC paths become \\?\UNC\server\share\...
+func longPath(p string) string {
+    // Same cut-off as the os package: leave room for an 8.3 file name in a directory
+    if len(p) < 248 || strings.HasPrefix(p, `\\?\`) {
+        return p
+    }
+    p = strings.ReplaceAll(p, "/", `\`)
+    if strings.HasPrefix(p, `\\`) {
+        return `\\?\UNC\` + p[2:]
+    }
+    if len(p) >= 3 && p[1] == ':' && p[2] == '\\' {
+        return `\\?\` + p
+    }
+    // relative or drive-relative paths cannot use the prefix
+    return p
+}